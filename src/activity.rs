@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::dsp::power_to_dbfs;
+
+// Intervals closer than this are treated as touching
+const CONTIGUOUS_TOLERANCE_S: f64 = 0.001;
+
+/// Anchors scan-relative times to the wall clock.
+#[derive(Clone, Copy, Debug)]
+pub struct ScanClock {
+    started_utc: DateTime<Utc>,
+}
+
+impl ScanClock {
+    pub fn start() -> Self {
+        ScanClock {
+            started_utc: Utc::now(),
+        }
+    }
+
+    pub fn started_utc(&self) -> DateTime<Utc> {
+        self.started_utc
+    }
+
+    pub fn utc(&self, since_start: Duration) -> DateTime<Utc> {
+        self.started_utc
+            + chrono::Duration::from_std(since_start).unwrap_or(chrono::Duration::zero())
+    }
+}
+
+/// A stretch of time a channel was heard, with its power over that stretch.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq)]
+pub struct ActivityInterval {
+    /// Seconds since the scan started
+    pub start_s: f64,
+    pub end_s: f64,
+    pub start_utc: DateTime<Utc>,
+    pub end_utc: DateTime<Utc>,
+    pub peak_dbfs: f64,
+    pub mean_dbfs: f64,
+}
+
+impl ActivityInterval {
+    pub fn new(
+        clock: &ScanClock,
+        start: Duration,
+        end: Duration,
+        peak_dbfs: f64,
+        mean_dbfs: f64,
+    ) -> Self {
+        ActivityInterval {
+            start_s: start.as_secs_f64(),
+            end_s: end.as_secs_f64(),
+            start_utc: clock.utc(start),
+            end_utc: clock.utc(end),
+            peak_dbfs,
+            mean_dbfs,
+        }
+    }
+
+    pub fn duration_s(&self) -> f64 {
+        self.end_s - self.start_s
+    }
+
+    fn touches(&self, other: &ActivityInterval) -> bool {
+        other.start_s <= self.end_s + CONTIGUOUS_TOLERANCE_S
+            && self.start_s <= other.end_s + CONTIGUOUS_TOLERANCE_S
+    }
+
+    /// Widens this interval to cover `other`. The mean is weighted by how
+    /// long each side lasted, in linear power.
+    fn absorb(&mut self, other: &ActivityInterval) {
+        let linear = |dbfs: f64| 10f64.powf(dbfs / 10.0);
+        let (own, theirs) = (self.duration_s(), other.duration_s());
+        self.mean_dbfs = if own + theirs > 0.0 {
+            power_to_dbfs(
+                (linear(self.mean_dbfs) * own + linear(other.mean_dbfs) * theirs) / (own + theirs),
+            )
+        } else {
+            self.mean_dbfs.max(other.mean_dbfs)
+        };
+        self.peak_dbfs = self.peak_dbfs.max(other.peak_dbfs);
+
+        if other.start_s < self.start_s {
+            self.start_s = other.start_s;
+            self.start_utc = other.start_utc;
+        }
+        if other.end_s > self.end_s {
+            self.end_s = other.end_s;
+            self.end_utc = other.end_utc;
+        }
+    }
+}
+
+/// Adds an interval to a time-ordered list, merging it with any it overlaps
+/// or touches.
+pub fn record(intervals: &mut Vec<ActivityInterval>, interval: ActivityInterval) {
+    let mut merged = interval;
+    intervals.retain(|existing| {
+        if existing.touches(&merged) {
+            merged.absorb(existing);
+            false
+        } else {
+            true
+        }
+    });
+    let position = intervals
+        .iter()
+        .position(|existing| existing.start_s > merged.start_s)
+        .unwrap_or(intervals.len());
+    intervals.insert(position, merged);
+}
+
+/// A stretch of the scan when nothing was being received, such as while
+/// the device was lost and being reconnected. Channels were not silent
+/// then, just unheard.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct CoverageGap {
+    /// Seconds since the scan started
+    pub start_s: f64,
+    pub end_s: f64,
+    pub start_utc: DateTime<Utc>,
+    pub end_utc: DateTime<Utc>,
+    pub reason: String,
+}
+
+impl CoverageGap {
+    pub fn new(clock: &ScanClock, start: Duration, end: Duration, reason: String) -> Self {
+        CoverageGap {
+            start_s: start.as_secs_f64(),
+            end_s: end.as_secs_f64(),
+            start_utc: clock.utc(start),
+            end_utc: clock.utc(end),
+            reason,
+        }
+    }
+}
+
+/// Adds a gap to a time-ordered list, running it on from the last one when
+/// the outage simply continued.
+pub fn record_gap(gaps: &mut Vec<CoverageGap>, gap: CoverageGap) {
+    match gaps.last_mut() {
+        Some(last) if gap.start_s <= last.end_s + CONTIGUOUS_TOLERANCE_S => {
+            last.end_s = last.end_s.max(gap.end_s);
+            last.end_utc = last.end_utc.max(gap.end_utc);
+        }
+        _ => gaps.push(gap),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(clock: &ScanClock, start_ms: u64, end_ms: u64, dbfs: f64) -> ActivityInterval {
+        ActivityInterval::new(
+            clock,
+            Duration::from_millis(start_ms),
+            Duration::from_millis(end_ms),
+            dbfs,
+            dbfs,
+        )
+    }
+
+    #[test]
+    fn merges_intervals_less_than_a_millisecond_apart() {
+        let clock = ScanClock::start();
+        let mut intervals = Vec::new();
+        record(&mut intervals, interval(&clock, 0, 10, -30.0));
+        record(
+            &mut intervals,
+            ActivityInterval::new(
+                &clock,
+                Duration::from_micros(10_500),
+                Duration::from_micros(20_000),
+                -20.0,
+                -20.0,
+            ),
+        );
+        assert_eq!(intervals.len(), 1);
+        assert_eq!(intervals[0].start_s, 0.0);
+        assert_eq!(intervals[0].end_s, 0.02);
+        assert_eq!(intervals[0].end_utc, clock.utc(Duration::from_millis(20)));
+        assert_eq!(intervals[0].peak_dbfs, -20.0);
+    }
+
+    #[test]
+    fn keeps_intervals_further_apart_separate_and_in_order() {
+        let clock = ScanClock::start();
+        let mut intervals = Vec::new();
+        record(&mut intervals, interval(&clock, 20, 30, -30.0));
+        record(&mut intervals, interval(&clock, 0, 10, -30.0));
+        record(&mut intervals, interval(&clock, 12, 18, -30.0));
+        let starts: Vec<f64> = intervals.iter().map(|i| i.start_s).collect();
+        assert_eq!(starts, [0.0, 0.012, 0.02]);
+    }
+
+    #[test]
+    fn an_interval_bridging_two_merges_all_three() {
+        let clock = ScanClock::start();
+        let mut intervals = Vec::new();
+        record(&mut intervals, interval(&clock, 0, 10, -30.0));
+        record(&mut intervals, interval(&clock, 20, 30, -30.0));
+        record(&mut intervals, interval(&clock, 10, 20, -30.0));
+        assert_eq!(intervals.len(), 1);
+        assert_eq!((intervals[0].start_s, intervals[0].end_s), (0.0, 0.03));
+    }
+
+    #[test]
+    fn weights_the_mean_by_duration() {
+        let clock = ScanClock::start();
+        let mut intervals = Vec::new();
+        record(&mut intervals, interval(&clock, 0, 30, -30.0));
+        record(&mut intervals, interval(&clock, 30, 40, -20.0));
+        // A quarter of the time at ten times the power
+        let expected = power_to_dbfs((3.0 * 1e-3 + 1e-2) / 4.0);
+        assert!((intervals[0].mean_dbfs - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,514 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::activity::{record, record_gap, ActivityInterval, CoverageGap, ScanClock};
+use crate::band::{Band, Link, Step};
+use crate::bsch::find_sync;
+use crate::burst::{classify, classify_uplink};
+use crate::carrier::{default_downlink, default_duplex_spacing, Carrier};
+use crate::config::Config;
+use crate::demod::{demodulate, Demodulated};
+use crate::detect::{cfar_detect, step_noise_floor, DetectionConfig};
+use crate::dsp::{decode_iq, iq_bytes, PowerAccumulator, BYTES_PER_SAMPLE};
+use crate::report::{
+    ChannelResult, DownlinkResult, ScanMetadata, ScanMode, ScanReport, UplinkResult,
+};
+use crate::slots::{carrier_occupancy, slot_occupancy};
+use crate::source::{DeviceInfo, Gains};
+use crate::spectrum::WelchSpectrum;
+use crate::sysinfo::{find_sysinfo, SysInfo};
+use crate::uplink::BurstDetector;
+
+/// What one dwell turned up, keyed by channel frequency in Hz.
+#[derive(Default)]
+pub struct Analysis {
+    /// What was found, line by line, for an instant scan to print
+    pub log: Vec<String>,
+    pub downlinks: Vec<(u64, DownlinkResult)>,
+    pub uplinks: Vec<(u64, UplinkResult)>,
+    /// (downlink, uplink) pairs of the carriers found
+    pub heard_pairs: Vec<(u64, u64)>,
+}
+
+/// Uplink paired with a downlink carrier, using the cell's own duplex
+/// settings when its SYSINFO was decoded and the band's usual split if not.
+fn predicted_uplink(carrier: &Carrier, sysinfo: Option<&SysInfo>) -> Option<u64> {
+    match sysinfo {
+        Some(info) => carrier.uplink_freq(info.duplex_spacing, info.reverse_operation),
+        None => {
+            default_duplex_spacing(carrier.band).and_then(|code| carrier.uplink_freq(code, false))
+        }
+    }
+}
+
+/// Downlink paired with an uplink channel: one already heard whose uplink
+/// lands on it if there is one, otherwise the band's usual duplex split.
+fn linked_downlink(uplink: u64, raster: u64, heard: &[(u64, u64)]) -> Option<u64> {
+    heard
+        .iter()
+        .find(|(_, paired)| paired.abs_diff(uplink) < raster / 2)
+        .map(|&(downlink, _)| downlink)
+        .or_else(|| default_downlink(uplink))
+}
+
+// Carriers and uplink bursts found are demodulated from the latest this
+// much of the dwell, so a long dwell does not mean holding all of it
+const DEMOD_WINDOW: Duration = Duration::from_secs(1);
+// and never more samples than this, whatever the sample rate: one second at
+// 8 Msps, 16 MB of I/Q per worker
+const DEMOD_MAX_SAMPLES: usize = 8_000_000;
+
+// Taken either side of an uplink burst, whose edges are only known to the
+// detector's frame
+const BURST_MARGIN: Duration = Duration::from_millis(1);
+
+/// The bytes of `burst` in a demodulation window that starts
+/// `window_start_s` into the scan, or `None` if it fell before the window.
+fn burst_iq<'b>(
+    iq: &'b [u8],
+    burst: &ActivityInterval,
+    window_start_s: f64,
+    sample_rate: u32,
+) -> Option<&'b [u8]> {
+    let byte = |at_s: f64| {
+        let sample = ((at_s - window_start_s) * sample_rate as f64).max(0.0) as usize;
+        (sample * BYTES_PER_SAMPLE).min(iq.len())
+    };
+    let margin = BURST_MARGIN.as_secs_f64();
+    let (first, last) = (byte(burst.start_s - margin), byte(burst.end_s + margin));
+    (first < last).then(|| &iq[first..last])
+}
+
+/// How a dwell was taken, as the capture side saw it.
+#[derive(Clone, Copy)]
+pub struct Tuning {
+    pub retune_latency: Duration,
+    pub gains: Option<Gains>,
+    /// When the first sample was read
+    pub started: Instant,
+}
+
+enum Listener<'a> {
+    Downlink(WelchSpectrum),
+    Uplink(BurstDetector<'a>),
+}
+
+/// One dwell, analysed a block at a time as it arrives. Only running
+/// statistics, a fixed-size spectrum or each channel's burst state, and
+/// the demodulation window are kept, so memory does not grow with the
+/// dwell.
+pub struct DwellAnalysis<'a> {
+    band: &'a Band,
+    step: &'a Step,
+    detection: &'a DetectionConfig,
+    clock: &'a ScanClock,
+    // When the scan started, for placing activity on its timeline
+    origin: Instant,
+    tuning: Tuning,
+    // Complex samples taken in so far
+    samples: usize,
+    power: PowerAccumulator,
+    listener: Listener<'a>,
+    // The raw bytes of the demodulation window
+    recent: VecDeque<u8>,
+}
+
+impl<'a> DwellAnalysis<'a> {
+    pub fn new(
+        band: &'a Band,
+        step: &'a Step,
+        detection: &'a DetectionConfig,
+        clock: &'a ScanClock,
+        origin: Instant,
+        tuning: Tuning,
+    ) -> Self {
+        let listener = match band.link {
+            Link::Downlink => Listener::Downlink(WelchSpectrum::new(
+                step.center_freq,
+                band.sample_rate,
+                &step.channels,
+                band.raster,
+            )),
+            Link::Uplink => {
+                let offset = tuning.started.duration_since(origin);
+                Listener::Uplink(BurstDetector::new(band, step, detection, clock, offset))
+            }
+        };
+        DwellAnalysis {
+            band,
+            step,
+            detection,
+            clock,
+            origin,
+            tuning,
+            samples: 0,
+            power: PowerAccumulator::default(),
+            listener,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn add(&mut self, block: &[u8]) {
+        let iq = decode_iq(block);
+        self.samples += iq.len();
+        self.power.add(&iq);
+        match &mut self.listener {
+            Listener::Downlink(spectrum) => spectrum.add(&iq),
+            Listener::Uplink(bursts) => bursts.add(&iq),
+        }
+        let window =
+            iq_bytes(self.band.sample_rate, DEMOD_WINDOW).min(DEMOD_MAX_SAMPLES * BYTES_PER_SAMPLE);
+        self.recent.extend(block);
+        let excess = self.recent.len().saturating_sub(window);
+        self.recent.drain(..excess);
+    }
+
+    /// When the dwell started and ended relative to the scan, going by the
+    /// samples actually received.
+    fn span(&self) -> (Duration, Duration) {
+        let start = self.tuning.started.duration_since(self.origin);
+        (
+            start,
+            start + Duration::from_secs_f64(self.samples as f64 / self.band.sample_rate as f64),
+        )
+    }
+
+    /// Looks for carriers, or uplink bursts on an uplink band, in what came
+    /// in.
+    pub fn finish(self) -> Analysis {
+        let mut analysis = Analysis::default();
+        let log = &mut analysis.log;
+        let (band, step, detection, clock) = (self.band, self.step, self.detection, self.clock);
+        let retune_ms = self.tuning.retune_latency.as_secs_f64() * 1000.0;
+        let gains = self.tuning.gains;
+        let sample_count = self.samples;
+
+        log.push(format!(
+            "Scanning frequency: {} MHz",
+            step.center_freq as f64 / 1_000_000.0
+        ));
+        log.push(format!(
+            "Received {} samples after {:.1} ms retune",
+            sample_count, retune_ms
+        ));
+        if let Some(gains) = gains {
+            log.push(format!(
+                "Gains: amp {}, LNA {} dB, VGA {} dB",
+                gains.amp, gains.lna, gains.vga
+            ));
+        }
+
+        let Some(stats) = self.power.stats() else {
+            log.push("No signal detected.".to_string());
+            return analysis;
+        };
+        log.push(format!(
+            "Capture power {:.2} dBFS mean, {:.2} dBFS RMS, {:.2} dBFS peak",
+            stats.mean_dbfs, stats.rms_dbfs, stats.peak_dbfs
+        ));
+
+        let (start, end) = self.span();
+        let mut recent = self.recent;
+        let iq = recent.make_contiguous();
+        let spectrum = match self.listener {
+            Listener::Downlink(spectrum) => spectrum,
+            Listener::Uplink(bursts) => {
+                // The window holds the end of the dwell
+                let window_start_s = start.as_secs_f64()
+                    + (sample_count - iq.len() / BYTES_PER_SAMPLE) as f64 / band.sample_rate as f64;
+                for activity in bursts.finish() {
+                    log.push(format!(
+                        "{} uplink bursts at {:.4} MHz",
+                        activity.bursts.len(),
+                        activity.freq as f64 / 1_000_000.0
+                    ));
+                    let offset = activity.freq as f64 - step.center_freq as f64;
+                    let demodulated: Vec<Demodulated> = activity
+                        .bursts
+                        .iter()
+                        .filter_map(|burst| burst_iq(iq, burst, window_start_s, band.sample_rate))
+                        .filter_map(|burst| demodulate(burst, band.sample_rate, offset))
+                        .collect();
+                    let classification =
+                        (!demodulated.is_empty()).then(|| classify_uplink(&demodulated));
+                    if let Some((modulation, confidence, trained)) = classification {
+                        log.push(format!(
+                            "Classified as {:?} ({:.0}% confidence): {} of {} demodulated bursts trained",
+                            modulation,
+                            confidence * 100.0,
+                            trained,
+                            demodulated.len()
+                        ));
+                    }
+                    let mut intervals = Vec::new();
+                    for burst in activity.bursts {
+                        record(&mut intervals, burst);
+                    }
+                    // Paired with its downlink once everything has been heard
+                    let heard = UplinkResult {
+                        freq: activity.freq as f64 / 1_000_000.0,
+                        band: band.name.clone(),
+                        downlink_freq: None,
+                        carrier: None,
+                        noise_floor_dbfs: activity.noise_floor_dbfs,
+                        sample_count,
+                        retune_ms,
+                        gains,
+                        modulation: classification.map(|(modulation, _, _)| modulation),
+                        confidence: classification.map(|(_, confidence, _)| confidence),
+                        activity: intervals,
+                    };
+                    analysis.uplinks.push((activity.freq, heard));
+                }
+                return analysis;
+            }
+        };
+
+        let peaks = spectrum.channel_peaks();
+        let Some(spectrum) = spectrum.spectrum() else {
+            log.push("Too few samples for a spectrum.".to_string());
+            return analysis;
+        };
+
+        let channels = spectrum.channel_powers(&step.channels, band.raster);
+        if let Some(floor) = step_noise_floor(&channels, detection) {
+            log.push(format!(
+                "Noise floor {:.2} dBFS per channel at this step",
+                floor
+            ));
+        }
+
+        for hit in cfar_detect(&channels, detection) {
+            log.push(format!(
+                "Signal detected at {:.4} MHz with strength {:.2} dBFS ({:.1} dB SNR)",
+                hit.channel.freq as f64 / 1_000_000.0,
+                hit.channel.power_dbfs,
+                hit.snr_db
+            ));
+            let offset = hit.channel.freq as f64 - step.center_freq as f64;
+            let demodulated = demodulate(iq, band.sample_rate, offset);
+            let classification = demodulated.as_ref().map(classify);
+            let mut sync = None;
+            let mut sysinfo = None;
+            let mut slots = None;
+            if let (Some(demodulated), Some(classification)) = (&demodulated, &classification) {
+                log.push(format!(
+                    "Demodulated {} symbols, {:.0} Hz off, {:.1}° phase error",
+                    demodulated.symbols.len(),
+                    demodulated.freq_offset_hz,
+                    demodulated.phase_error_rms.to_degrees()
+                ));
+                log.push(format!(
+                "Classified as {:?} ({:.0}% confidence): {} of {} slots trained, {} sync bursts, {:.1} training bit errors on average",
+                classification.modulation,
+                classification.confidence * 100.0,
+                classification.bursts.len(),
+                classification.slots,
+                classification.sync_bursts(),
+                classification.mean_training_errors()
+            ));
+                sync = find_sync(&demodulated.bits, classification);
+                if let Some(sync) = &sync {
+                    log.push(format!(
+                        "Cell MCC {} MNC {} colour code {}, timeslot {} frame {} multiframe {}",
+                        sync.mcc,
+                        sync.mnc,
+                        sync.colour_code,
+                        sync.timeslot,
+                        sync.frame,
+                        sync.multiframe
+                    ));
+                    sysinfo = find_sysinfo(&demodulated.bits, classification, sync);
+                }
+                if let Some(sysinfo) = &sysinfo {
+                    log.push(format!(
+                        "Main carrier {} at {:.5} MHz, location area {}, security class {}",
+                        sysinfo.main_carrier,
+                        sysinfo.main_carrier().downlink_freq() as f64 / 1_000_000.0,
+                        sysinfo.location_area,
+                        sysinfo.security_class
+                    ));
+                }
+                slots = slot_occupancy(&demodulated.bits, classification);
+                for slot in slots.iter().flatten() {
+                    log.push(format!(
+                        "Timeslot {}: {} traffic, {} control, {} idle bursts ({:.0}% occupied)",
+                        slot.timeslot,
+                        slot.traffic,
+                        slot.control,
+                        slot.idle,
+                        slot.occupancy_percent
+                    ));
+                }
+            }
+            // A carrier off the raster shows up in the channels either side,
+            // so it is placed by what the demodulator measured where it can be
+            let measured = demodulated
+                .as_ref()
+                .map(|d| d.freq_offset_hz)
+                .filter(|offset| offset.abs() < band.raster as f64)
+                .map_or(hit.channel.freq, |offset| {
+                    (hit.channel.freq as f64 + offset).round() as u64
+                });
+            let carrier = Carrier::from_downlink(measured);
+            let carrier_freq = carrier.downlink_freq();
+            let uplink_freq = predicted_uplink(&carrier, sysinfo.as_ref());
+            if let Some(uplink) = uplink_freq {
+                analysis.heard_pairs.push((carrier_freq, uplink));
+            }
+            let peak = step
+                .channels
+                .iter()
+                .zip(&peaks)
+                .find(|(&freq, _)| freq == hit.channel.freq)
+                .map_or(hit.channel.power_dbfs, |(_, &peak)| peak);
+            let heard = ActivityInterval::new(clock, start, end, peak, hit.channel.power_dbfs);
+            log.push(format!(
+                "Carrier {} in band {} (offset code {}), uplink {}",
+                carrier.number,
+                carrier.band,
+                carrier.offset,
+                uplink_freq.map_or("unknown".to_string(), |f| {
+                    format!("{:.5} MHz", f as f64 / 1_000_000.0)
+                })
+            ));
+            let control_channel = sysinfo.map(|info| {
+                info.main_carrier().downlink_freq().abs_diff(carrier_freq) < band.raster / 2
+            });
+            let result = DownlinkResult {
+                freq: carrier_freq as f64 / 1_000_000.0,
+                band: band.name.clone(),
+                carrier,
+                uplink_freq: uplink_freq.map(|f| f as f64 / 1_000_000.0),
+                strength_dbfs: hit.channel.power_dbfs,
+                peak_dbfs: hit.channel.peak_bin_dbfs,
+                noise_floor_dbfs: hit.noise_floor_dbfs,
+                snr_db: hit.snr_db,
+                sample_count,
+                retune_ms,
+                gains,
+                carrier_offset_hz: demodulated
+                    .as_ref()
+                    .map(|_| measured as f64 - carrier_freq as f64),
+                phase_error_deg: demodulated.as_ref().map(|d| d.phase_error_rms.to_degrees()),
+                modulation: classification.as_ref().map(|c| c.modulation),
+                confidence: classification.as_ref().map(|c| c.confidence),
+                sync,
+                sysinfo,
+                control_channel,
+                slot_occupancy_percent: slots.as_deref().map(carrier_occupancy),
+                slots,
+                activity: vec![heard],
+            };
+            // Channels either side only catch the carrier's edge, which pulls
+            // their offsets short, so a carrier is matched within half a raster
+            let same_carrier = |(freq, kept): &(u64, DownlinkResult)| {
+                let kept_measured = *freq as f64 + kept.carrier_offset_hz.unwrap_or(0.0);
+                *freq == carrier_freq
+                    || (kept_measured - measured as f64).abs() < band.raster as f64 / 2.0
+            };
+            match analysis
+                .downlinks
+                .iter_mut()
+                .find(|kept| same_carrier(kept))
+            {
+                Some(kept) => {
+                    log.push(
+                        "Same carrier as a neighbouring channel, keeping the stronger".to_string(),
+                    );
+                    if result.strength_dbfs > kept.1.strength_dbfs {
+                        *kept = (carrier_freq, result);
+                    }
+                }
+                None => analysis.downlinks.push((carrier_freq, result)),
+            }
+        }
+        analysis
+    }
+}
+
+/// Everything heard so far, with each carrier's activity merged in from
+/// every dwell it turned up in, whatever order the dwells were analysed in.
+#[derive(Default)]
+pub struct Findings {
+    downlinks: HashMap<u64, DownlinkResult>,
+    uplinks: HashMap<u64, UplinkResult>,
+    // (downlink, uplink) pairs of every carrier found so far
+    heard_pairs: Vec<(u64, u64)>,
+    // When the device was lost and nothing could be heard
+    gaps: Vec<CoverageGap>,
+    dropped_blocks: u64,
+}
+
+impl Findings {
+    pub fn add(&mut self, analysis: Analysis) {
+        for (freq, heard) in analysis.downlinks {
+            match self.downlinks.entry(freq) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(heard),
+                Entry::Vacant(entry) => {
+                    entry.insert(heard);
+                }
+            }
+        }
+        for (freq, heard) in analysis.uplinks {
+            match self.uplinks.entry(freq) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(heard),
+                Entry::Vacant(entry) => {
+                    entry.insert(heard);
+                }
+            }
+        }
+        for pair in analysis.heard_pairs {
+            if !self.heard_pairs.contains(&pair) {
+                self.heard_pairs.push(pair);
+            }
+        }
+    }
+
+    pub fn add_gap(&mut self, gap: CoverageGap) {
+        record_gap(&mut self.gaps, gap);
+    }
+
+    pub fn add_dropped(&mut self, blocks: usize) {
+        self.dropped_blocks += blocks as u64;
+    }
+
+    pub fn report(
+        &self,
+        mode: ScanMode,
+        device: DeviceInfo,
+        clock: &ScanClock,
+        config: &Config,
+        bands: &[Band],
+        completed: bool,
+    ) -> ScanReport {
+        let uplinks = self.uplinks.iter().map(|(&freq, result)| {
+            let raster = bands
+                .iter()
+                .find(|band| band.name == result.band)
+                .map_or(0, |band| band.raster);
+            let downlink = linked_downlink(freq, raster, &self.heard_pairs);
+            UplinkResult {
+                downlink_freq: downlink.map(|f| f as f64 / 1_000_000.0),
+                carrier: downlink.map(Carrier::from_downlink),
+                ..result.clone()
+            }
+        });
+        let results: Vec<ChannelResult> = self
+            .downlinks
+            .values()
+            .cloned()
+            .map(ChannelResult::Downlink)
+            .chain(uplinks.map(ChannelResult::Uplink))
+            .collect();
+        let metadata = ScanMetadata {
+            dropped_blocks: self.dropped_blocks,
+            ..ScanMetadata::new(mode, device, clock, config, bands, completed)
+        };
+        ScanReport {
+            coverage_gaps: self.gaps.clone(),
+            ..ScanReport::new(metadata, results)
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use schemars::JsonSchema;
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use crate::gain::GainConfig;
+
+// Share of the sampled bandwidth clear of the HackRF's baseband filter roll-off
+const USABLE_BANDWIDTH: f64 = 0.75;
+
+fn default_raster() -> u64 {
+    25_000
+}
+
+fn default_dwell_ms() -> u64 {
+    1_000
+}
+
+fn default_sample_rate() -> u32 {
+    2_000_000
+}
+
+/// Which half of a duplex allocation a band covers. Downlinks are
+/// continuous base station carriers; uplinks carry short mobile bursts.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Link {
+    #[default]
+    Downlink,
+    Uplink,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Band {
+    pub name: String,
+    pub start_freq: u64,
+    pub end_freq: u64,
+    /// Channel spacing; carriers sit in the middle of each raster slot
+    #[serde(default = "default_raster")]
+    pub raster: u64,
+    /// Time spent on each tuning step
+    #[serde(default = "default_dwell_ms")]
+    pub dwell_ms: u64,
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u32,
+    /// Overrides the top-level gain settings for this band
+    #[serde(default)]
+    pub gain: Option<GainConfig>,
+    #[serde(default)]
+    pub link: Link,
+}
+
+/// A band in the config is either the name of a built-in preset or a full
+/// definition.
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+#[serde(untagged)]
+pub enum BandSpec {
+    Preset(String),
+    Custom(Band),
+}
+
+// By hand rather than untagged, so a mistake inside a band definition is
+// reported against its field instead of as matching neither form
+impl<'de> Deserialize<'de> for BandSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpecVisitor;
+
+        impl<'de> Visitor<'de> for SpecVisitor {
+            type Value = BandSpec;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a band preset name or a band definition")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<BandSpec, E> {
+                Ok(BandSpec::Preset(name.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<BandSpec, A::Error> {
+                Band::deserialize(MapAccessDeserializer::new(map)).map(BandSpec::Custom)
+            }
+        }
+
+        deserializer.deserialize_any(SpecVisitor)
+    }
+}
+
+/// One tuning of the radio and the channels it is responsible for.
+#[derive(Clone)]
+pub struct Step {
+    pub center_freq: u64,
+    pub channels: Vec<u64>,
+}
+
+/// Built-in allocations, each half of a duplex pair on its own so that
+/// terminal bursts are never taken for base station carriers.
+pub const PRESETS: &[(&str, u64, u64, Link)] = &[
+    // Harmonised European emergency services allocation: base stations
+    // transmit in the upper half, terminals 10 MHz below
+    (
+        "public-safety-390-400",
+        390_000_000,
+        400_000_000,
+        Link::Downlink,
+    ),
+    (
+        "public-safety-uplink-380-390",
+        380_000_000,
+        390_000_000,
+        Link::Uplink,
+    ),
+    (
+        "commercial-420-430",
+        420_000_000,
+        430_000_000,
+        Link::Downlink,
+    ),
+    (
+        "commercial-uplink-410-420",
+        410_000_000,
+        420_000_000,
+        Link::Uplink,
+    ),
+    (
+        "commercial-460-470",
+        460_000_000,
+        470_000_000,
+        Link::Downlink,
+    ),
+    (
+        "commercial-uplink-450-460",
+        450_000_000,
+        460_000_000,
+        Link::Uplink,
+    ),
+    // 45 MHz split: terminals at 870-876, base stations at 915-921
+    (
+        "commercial-915-921",
+        915_000_000,
+        921_000_000,
+        Link::Downlink,
+    ),
+    (
+        "commercial-uplink-870-876",
+        870_000_000,
+        876_000_000,
+        Link::Uplink,
+    ),
+];
+
+pub fn preset(name: &str) -> Option<Band> {
+    PRESETS
+        .iter()
+        .find(|(preset, _, _, _)| *preset == name)
+        .map(|&(name, start_freq, end_freq, link)| Band {
+            name: name.to_string(),
+            start_freq,
+            end_freq,
+            raster: default_raster(),
+            dwell_ms: default_dwell_ms(),
+            sample_rate: default_sample_rate(),
+            gain: None,
+            link,
+        })
+}
+
+/// 380-420 MHz, split at the allocation boundaries so each part is
+/// listened to as the link it carries.
+pub fn default_bands() -> Vec<BandSpec> {
+    vec![
+        BandSpec::Preset("public-safety-uplink-380-390".to_string()),
+        BandSpec::Preset("public-safety-390-400".to_string()),
+        // Outside the harmonised allocations; whatever sits here is taken
+        // as a continuous carrier
+        BandSpec::Custom(Band {
+            name: "downlink-400-410".to_string(),
+            start_freq: 400_000_000,
+            end_freq: 410_000_000,
+            raster: default_raster(),
+            dwell_ms: default_dwell_ms(),
+            sample_rate: default_sample_rate(),
+            gain: None,
+            link: Link::Downlink,
+        }),
+        BandSpec::Preset("commercial-uplink-410-420".to_string()),
+    ]
+}
+
+pub fn resolve_bands(specs: &[BandSpec]) -> Result<Vec<Band>, String> {
+    specs
+        .iter()
+        .map(|spec| match spec {
+            BandSpec::Custom(band) => Ok(band.clone()),
+            BandSpec::Preset(name) => preset(name).ok_or_else(|| {
+                let known: Vec<&str> = PRESETS.iter().map(|(name, _, _, _)| *name).collect();
+                format!(
+                    "Unknown band preset '{}', expected one of: {}",
+                    name,
+                    known.join(", ")
+                )
+            }),
+        })
+        .collect()
+}
+
+impl Band {
+    pub fn dwell(&self) -> Duration {
+        Duration::from_millis(self.dwell_ms)
+    }
+
+    /// Every channel centre in the band, on the raster counted from
+    /// `start_freq`.
+    pub fn channels(&self) -> Vec<u64> {
+        let mut channels = Vec::new();
+        let mut low = self.start_freq;
+        while low + self.raster <= self.end_freq {
+            channels.push(low + self.raster / 2);
+            low += self.raster;
+        }
+        channels
+    }
+
+    /// Bandwidth of one tuning that carriers can be measured in.
+    pub fn usable_bandwidth(&self) -> u64 {
+        (self.sample_rate as f64 * USABLE_BANDWIDTH) as u64
+    }
+
+    /// Splits the band into tunings of an even number of channels each, so
+    /// the DC spike always lands on a channel edge rather than a carrier.
+    pub fn steps(&self) -> Vec<Step> {
+        let per_step = ((self.usable_bandwidth() / self.raster) as usize & !1).max(2);
+        let step_width = per_step as u64 * self.raster;
+
+        self.channels()
+            .chunks(per_step)
+            .map(|channels| Step {
+                center_freq: channels[0] - self.raster / 2 + step_width / 2,
+                channels: channels.to_vec(),
+            })
+            .collect()
+    }
+}
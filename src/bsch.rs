@@ -0,0 +1,62 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::burst::{BurstKind, Classification};
+use crate::coding::{decode_block, field, BROADCAST_SCRAMBLING};
+
+// The scrambled BSCH block (sb) inside a synchronisation burst
+const SB_OFFSET: usize = 94;
+const SB_BITS: usize = 120;
+const SB_INTERLEAVE_A: usize = 11;
+
+/// Contents of the SYNC PDU and the MLE-SYNC it carries (EN 300 392-2
+/// clause 21.4.4.2 and 18.4.2.1).
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncInfo {
+    pub system_code: u8,
+    pub colour_code: u8,
+    /// 1 to 4
+    pub timeslot: u8,
+    /// 1 to 18
+    pub frame: u8,
+    /// 1 to 60
+    pub multiframe: u8,
+    pub sharing_mode: u8,
+    pub mcc: u16,
+    pub mnc: u16,
+    pub cell_service_level: u8,
+    pub late_entry: bool,
+}
+
+/// Decodes the BSCH of the synchronisation burst starting at `burst_start`
+/// in the demodulated bit stream.
+pub fn decode_bsch(bits: &[u8], burst_start: usize) -> Option<SyncInfo> {
+    let sb = bits.get(burst_start + SB_OFFSET..burst_start + SB_OFFSET + SB_BITS)?;
+    let pdu = decode_block(sb, BROADCAST_SCRAMBLING, SB_INTERLEAVE_A)?;
+
+    Some(SyncInfo {
+        system_code: field(&pdu, 0, 4) as u8,
+        colour_code: field(&pdu, 4, 6) as u8,
+        timeslot: field(&pdu, 10, 2) as u8 + 1,
+        frame: field(&pdu, 12, 5) as u8,
+        multiframe: field(&pdu, 17, 6) as u8,
+        sharing_mode: field(&pdu, 23, 2) as u8,
+        // TS reserved frames, U-plane DTX, frame 18 extension and a
+        // reserved bit take up the rest of the SYNC PDU
+        mcc: field(&pdu, 31, 10) as u16,
+        mnc: field(&pdu, 41, 14) as u16,
+        // Neighbour cell broadcast sits in between
+        cell_service_level: field(&pdu, 57, 2) as u8,
+        late_entry: field(&pdu, 59, 1) == 1,
+    })
+}
+
+/// The first synchronisation burst of a classified carrier whose BSCH
+/// decodes cleanly.
+pub fn find_sync(bits: &[u8], classification: &Classification) -> Option<SyncInfo> {
+    classification
+        .bursts
+        .iter()
+        .filter(|burst| burst.kind == BurstKind::Sync)
+        .find_map(|burst| decode_bsch(bits, burst.start))
+}
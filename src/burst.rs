@@ -0,0 +1,290 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::demod::Demodulated;
+
+/// Bits in one timeslot of a continuous downlink (255 symbols).
+pub const BURST_BITS: usize = 510;
+
+// Training sequences from EN 300 392-2 clause 9.4.4.3
+pub const NORMAL_TRAINING_1: [u8; 22] = [
+    1, 1, 0, 1, 0, 0, 0, 0, 1, 1, 1, 0, 1, 0, 0, 1, 1, 1, 0, 1, 0, 0,
+];
+pub const NORMAL_TRAINING_2: [u8; 22] = [
+    0, 1, 1, 1, 1, 0, 1, 0, 0, 1, 0, 0, 0, 0, 1, 1, 0, 1, 1, 1, 1, 0,
+];
+pub const SYNC_TRAINING: [u8; 38] = [
+    1, 1, 0, 0, 0, 0, 0, 1, 1, 0, 0, 1, 1, 1, 0, 0, 1, 1, 1, 0, 1, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 1, 1, 1,
+];
+/// Sent in the middle of a control uplink burst only.
+pub const EXTENDED_TRAINING: [u8; 30] = [
+    1, 0, 0, 1, 1, 1, 0, 1, 0, 0, 0, 0, 1, 1, 1, 0, 1, 0, 0, 1, 1, 1, 0, 1, 0, 0, 0, 0, 1, 1,
+];
+
+// Half-slot signalling blocks inside a downlink burst: the second half of a
+// synchronisation burst, or either half of a normal burst, whose two halves
+// also read as one full slot
+pub const FIRST_HALF_OFFSET: usize = 14;
+pub const SECOND_HALF_OFFSET: usize = 282;
+pub const HALF_SLOT_BITS: usize = 216;
+
+// Where the training sequence sits inside each kind of downlink burst
+const NORMAL_TRAINING_OFFSET: usize = 244;
+const SYNC_TRAINING_OFFSET: usize = 214;
+// Bit errors tolerated when matching each training sequence
+const NORMAL_TRAINING_ERRORS: usize = 2;
+const SYNC_TRAINING_ERRORS: usize = 4;
+const EXTENDED_TRAINING_ERRORS: usize = 3;
+
+// Share of slots, or uplink bursts, that must carry a training sequence to
+// call it TETRA
+const TETRA_MIN_CONFIDENCE: f64 = 0.5;
+// Share of identical symbols above which the carrier is taken as unmodulated
+const UNMODULATED_MIN_SHARE: f64 = 0.9;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Modulation {
+    Tetra,
+    /// A steady carrier or spur with no phase modulation
+    Unmodulated,
+    /// Something else: DMR, P25, analogue FM, noise...
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BurstKind {
+    /// Normal downlink burst with training sequence 1: one full-slot block
+    NormalFull,
+    /// Normal downlink burst with training sequence 2: two half-slot blocks
+    NormalSplit,
+    /// Synchronisation downlink burst
+    Sync,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Burst {
+    /// Index of the burst's first bit in the demodulated stream
+    pub start: usize,
+    pub kind: BurstKind,
+    /// Bit errors in the training sequence
+    pub errors: usize,
+}
+
+pub struct Classification {
+    pub modulation: Modulation,
+    pub confidence: f64,
+    /// Whole slots in the capture once aligned to the burst grid
+    pub slots: usize,
+    pub bursts: Vec<Burst>,
+}
+
+fn symbol_counts(bits: &[u8]) -> [usize; 4] {
+    let mut counts = [0usize; 4];
+    for dibit in bits.chunks_exact(2) {
+        counts[(dibit[0] * 2 + dibit[1]) as usize] += 1;
+    }
+    counts
+}
+
+/// Calls it TETRA when enough of the slots or bursts were trained, and
+/// otherwise tells a bare carrier from anything else by its symbols.
+fn judge(hit_share: f64, symbol_counts: &[usize; 4]) -> (Modulation, f64) {
+    let total_symbols = symbol_counts.iter().sum::<usize>().max(1);
+    let dominant_share = *symbol_counts.iter().max().unwrap_or(&0) as f64 / total_symbols as f64;
+
+    if hit_share >= TETRA_MIN_CONFIDENCE {
+        (Modulation::Tetra, hit_share)
+    } else if dominant_share >= UNMODULATED_MIN_SHARE {
+        (Modulation::Unmodulated, dominant_share)
+    } else {
+        (Modulation::Unknown, 1.0 - hit_share)
+    }
+}
+
+fn errors_at(bits: &[u8], offset: usize, sequence: &[u8]) -> usize {
+    bits[offset..offset + sequence.len()]
+        .iter()
+        .zip(sequence)
+        .filter(|(a, b)| a != b)
+        .count()
+}
+
+fn match_burst(bits: &[u8], start: usize) -> Option<(BurstKind, usize)> {
+    let sync = errors_at(bits, start + SYNC_TRAINING_OFFSET, &SYNC_TRAINING);
+    if sync <= SYNC_TRAINING_ERRORS {
+        return Some((BurstKind::Sync, sync));
+    }
+    let offset = start + NORMAL_TRAINING_OFFSET;
+    let full = errors_at(bits, offset, &NORMAL_TRAINING_1);
+    let split = errors_at(bits, offset, &NORMAL_TRAINING_2);
+    if full <= NORMAL_TRAINING_ERRORS && full <= split {
+        Some((BurstKind::NormalFull, full))
+    } else if split <= NORMAL_TRAINING_ERRORS {
+        Some((BurstKind::NormalSplit, split))
+    } else {
+        None
+    }
+}
+
+fn bursts_at(bits: &[u8], phase: usize) -> (usize, Vec<Burst>) {
+    let mut slots = 0;
+    let mut bursts = Vec::new();
+    let mut start = phase;
+    while start + BURST_BITS <= bits.len() {
+        if let Some((kind, errors)) = match_burst(bits, start) {
+            bursts.push(Burst {
+                start,
+                kind,
+                errors,
+            });
+        }
+        slots += 1;
+        start += BURST_BITS;
+    }
+    (slots, bursts)
+}
+
+/// Finds the slot grid with the most training sequence hits and uses it to
+/// decide whether the carrier is TETRA.
+pub fn classify(demodulated: &Demodulated) -> Classification {
+    let bits = &demodulated.bits;
+
+    // Bursts start on a symbol, so only even bit offsets are candidates
+    let (slots, bursts) = (0..BURST_BITS)
+        .step_by(2)
+        .map(|phase| bursts_at(bits, phase))
+        .max_by_key(|(_, bursts)| bursts.len())
+        .unwrap_or((0, Vec::new()));
+
+    let hit_share = if slots > 0 {
+        bursts.len() as f64 / slots as f64
+    } else {
+        0.0
+    };
+
+    let (modulation, confidence) = judge(hit_share, &symbol_counts(bits));
+    Classification {
+        modulation,
+        confidence,
+        slots,
+        bursts,
+    }
+}
+
+impl Classification {
+    pub fn sync_bursts(&self) -> usize {
+        self.bursts
+            .iter()
+            .filter(|burst| burst.kind == BurstKind::Sync)
+            .count()
+    }
+
+    pub fn mean_training_errors(&self) -> f64 {
+        if self.bursts.is_empty() {
+            return 0.0;
+        }
+        let errors: usize = self.bursts.iter().map(|burst| burst.errors).sum();
+        errors as f64 / self.bursts.len() as f64
+    }
+}
+
+/// Whether one uplink transmission carries a training sequence: the
+/// extended one of a control uplink burst or either normal one of a normal
+/// uplink burst (clause 9.4.2). Burst edges are only known to within a
+/// frame of the detector, so every symbol position is tried.
+fn uplink_trained(bits: &[u8]) -> bool {
+    let found = |sequence: &[u8], tolerated: usize| {
+        (0..=bits.len().saturating_sub(sequence.len()))
+            .step_by(2)
+            .any(|offset| {
+                offset + sequence.len() <= bits.len()
+                    && errors_at(bits, offset, sequence) <= tolerated
+            })
+    };
+    found(&EXTENDED_TRAINING, EXTENDED_TRAINING_ERRORS)
+        || found(&NORMAL_TRAINING_1, NORMAL_TRAINING_ERRORS)
+        || found(&NORMAL_TRAINING_2, NORMAL_TRAINING_ERRORS)
+}
+
+/// Decides whether an uplink channel is TETRA from the bursts demodulated
+/// on it, by the share of them that carry an uplink training sequence.
+/// Returns the verdict, its confidence and how many bursts were trained.
+pub fn classify_uplink(bursts: &[Demodulated]) -> (Modulation, f64, usize) {
+    let trained = bursts
+        .iter()
+        .filter(|burst| uplink_trained(&burst.bits))
+        .count();
+    let hit_share = trained as f64 / bursts.len().max(1) as f64;
+
+    let mut counts = [0usize; 4];
+    for burst in bursts {
+        for (total, count) in counts.iter_mut().zip(symbol_counts(&burst.bits)) {
+            *total += count;
+        }
+    }
+    let (modulation, confidence) = judge(hit_share, &counts);
+    (modulation, confidence, trained)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+
+    fn demodulated(bits: Vec<u8>) -> Demodulated {
+        Demodulated {
+            symbols: vec![Complex::new(0.0, 0.0); bits.len() / 2],
+            bits,
+            freq_offset_hz: 0.0,
+            phase_error_rms: 0.0,
+        }
+    }
+
+    fn pseudo_random_bits(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 31) as u8
+            })
+            .collect()
+    }
+
+    /// A control uplink burst: tail, 84 bits, the extended training
+    /// sequence, 84 bits and tail, with some noise either side.
+    fn control_uplink_burst(seed: u32, lead: usize) -> Vec<u8> {
+        let mut bits = pseudo_random_bits(lead + 88, seed);
+        bits.extend_from_slice(&EXTENDED_TRAINING);
+        bits.extend(pseudo_random_bits(88 + 20, seed ^ 0xFFFF));
+        bits
+    }
+
+    #[test]
+    fn finds_the_extended_training_sequence_wherever_the_burst_starts() {
+        let bursts: Vec<Demodulated> = (0..4)
+            .map(|i| demodulated(control_uplink_burst(i, 2 * i as usize)))
+            .collect();
+        let (modulation, confidence, trained) = classify_uplink(&bursts);
+        assert_eq!(modulation, Modulation::Tetra);
+        assert_eq!(trained, 4);
+        assert_eq!(confidence, 1.0);
+    }
+
+    #[test]
+    fn untrained_bursts_are_not_tetra() {
+        let bursts: Vec<Demodulated> = (0..4)
+            .map(|i| demodulated(pseudo_random_bits(200, 77 + i)))
+            .collect();
+        let (modulation, _, trained) = classify_uplink(&bursts);
+        assert_eq!(modulation, Modulation::Unknown);
+        assert_eq!(trained, 0);
+    }
+
+    #[test]
+    fn a_steady_carrier_is_unmodulated() {
+        let (modulation, _, _) = classify_uplink(&[demodulated(vec![0; 400])]);
+        assert_eq!(modulation, Modulation::Unmodulated);
+    }
+}
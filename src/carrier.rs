@@ -0,0 +1,161 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const BAND_WIDTH_HZ: u64 = 100_000_000;
+const CARRIER_SPACING_HZ: u64 = 25_000;
+
+// Duplex spacing in kHz by duplex spacing code and frequency band, -1 where
+// the combination is reserved (EN 300 392-15)
+const DUPLEX_SPACING_KHZ: [[i32; 16]; 8] = [
+    [
+        -1, 1600, 10000, 10000, 10000, 10000, 10000, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    [
+        -1, 4500, -1, 36000, 7000, -1, -1, -1, 45000, 45000, -1, -1, -1, -1, -1, -1,
+    ],
+    [-1, 0, 0, 0, 0, 0, 0, -1, 0, 0, -1, -1, -1, -1, -1, -1],
+    [
+        -1, -1, -1, 8000, 8000, -1, -1, -1, 18000, 18000, -1, -1, -1, -1, -1, -1,
+    ],
+    [
+        -1, -1, -1, 18000, 5000, -1, 30000, 30000, -1, 39000, -1, -1, -1, -1, -1, -1,
+    ],
+    [
+        -1, -1, -1, -1, 9500, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+    ],
+    [-1; 16],
+    [-1; 16],
+];
+
+/// A carrier as numbered over the air: 100 MHz band, 25 kHz carrier
+/// number within it and one of four small offsets.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Carrier {
+    pub band: u8,
+    pub number: u16,
+    /// 0 none, 1 +6.25 kHz, 2 -6.25 kHz, 3 +12.5 kHz
+    pub offset: u8,
+}
+
+fn offset_hz(code: u8) -> i64 {
+    match code {
+        1 => 6_250,
+        2 => -6_250,
+        3 => 12_500,
+        _ => 0,
+    }
+}
+
+/// Spacing between the paired downlink and uplink, if the code is defined
+/// for the band.
+pub fn duplex_spacing_hz(band: u8, code: u8) -> Option<u64> {
+    let khz = *DUPLEX_SPACING_KHZ.get(code as usize)?.get(band as usize)?;
+    (khz >= 0).then(|| khz as u64 * 1000)
+}
+
+/// Spacing code to assume for a band when no SYSINFO has been heard: the
+/// first one the band defines. That gives the usual 10 MHz split below
+/// 500 MHz and 45 MHz in the 800/900 MHz bands.
+pub fn default_duplex_spacing(band: u8) -> Option<u8> {
+    (0..DUPLEX_SPACING_KHZ.len() as u8).find(|&code| duplex_spacing_hz(band, code).is_some())
+}
+
+/// Downlink paired with an uplink frequency under the band's usual duplex
+/// split, for when the cell's own SYSINFO has not been heard.
+pub fn default_downlink(uplink_freq: u64) -> Option<u64> {
+    let band = (uplink_freq / BAND_WIDTH_HZ) as u8;
+    let spacing = duplex_spacing_hz(band, default_duplex_spacing(band)?)?;
+    Some(uplink_freq + spacing)
+}
+
+impl Carrier {
+    /// The carrier whose downlink is closest to `freq`.
+    pub fn from_downlink(freq: u64) -> Carrier {
+        let band = freq / BAND_WIDTH_HZ;
+        let within = freq % BAND_WIDTH_HZ;
+        // Offsets run from -6.25 to +12.5 kHz, so round from 6.25 kHz below
+        let number = (within + 6_250) / CARRIER_SPACING_HZ;
+        let residual = within as i64 - (number * CARRIER_SPACING_HZ) as i64;
+        let offset = (0..4)
+            .min_by_key(|&code| (residual - offset_hz(code)).abs())
+            .unwrap_or(0);
+
+        Carrier {
+            band: band as u8,
+            number: number as u16,
+            offset,
+        }
+    }
+
+    pub fn downlink_freq(&self) -> u64 {
+        let nominal = self.band as u64 * BAND_WIDTH_HZ + self.number as u64 * CARRIER_SPACING_HZ;
+        (nominal as i64 + offset_hz(self.offset)) as u64
+    }
+
+    /// The paired uplink: below the downlink normally, above it when the
+    /// cell signals reverse operation.
+    pub fn uplink_freq(&self, duplex_spacing: u8, reverse_operation: bool) -> Option<u64> {
+        let spacing = duplex_spacing_hz(self.band, duplex_spacing)?;
+        let downlink = self.downlink_freq();
+        if reverse_operation {
+            Some(downlink + spacing)
+        } else {
+            downlink.checked_sub(spacing)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carrier(band: u8, number: u16, offset: u8) -> Carrier {
+        Carrier {
+            band,
+            number,
+            offset,
+        }
+    }
+
+    #[test]
+    fn numbers_carriers_on_the_raster_and_either_side_of_it() {
+        assert_eq!(Carrier::from_downlink(390_525_000), carrier(3, 3621, 0));
+        assert_eq!(Carrier::from_downlink(390_512_500), carrier(3, 3620, 3));
+        assert_eq!(Carrier::from_downlink(390_506_250), carrier(3, 3620, 1));
+        assert_eq!(Carrier::from_downlink(390_493_750), carrier(3, 3620, 2));
+        // From 6.25 kHz below a carrier it belongs to that carrier, not
+        // 18.75 kHz above the previous one
+        assert_eq!(Carrier::from_downlink(390_518_750), carrier(3, 3621, 2));
+        assert_eq!(Carrier::from_downlink(399_993_750), carrier(3, 4000, 2));
+        assert_eq!(Carrier::from_downlink(400_000_000), carrier(4, 0, 0));
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_offset() {
+        assert_eq!(Carrier::from_downlink(390_525_400), carrier(3, 3621, 0));
+        assert_eq!(Carrier::from_downlink(390_510_900), carrier(3, 3620, 3));
+        assert_eq!(Carrier::from_downlink(390_508_000), carrier(3, 3620, 1));
+    }
+
+    #[test]
+    fn downlink_frequency_round_trips() {
+        for freq in [
+            390_525_000,
+            390_512_500,
+            390_506_250,
+            390_493_750,
+            425_000_000,
+        ] {
+            assert_eq!(Carrier::from_downlink(freq).downlink_freq(), freq);
+        }
+    }
+
+    #[test]
+    fn pairs_the_uplink_by_duplex_spacing() {
+        let carrier = carrier(3, 3621, 0);
+        assert_eq!(carrier.uplink_freq(0, false), Some(380_525_000));
+        assert_eq!(carrier.uplink_freq(0, true), Some(400_525_000));
+        assert_eq!(carrier.uplink_freq(7, false), None);
+        assert_eq!(default_downlink(380_525_000), Some(390_525_000));
+    }
+}
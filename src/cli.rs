@@ -0,0 +1,136 @@
+use clap::{Args, Parser, Subcommand};
+
+use crate::band::BandSpec;
+use crate::config::Config;
+use crate::gain::{GainConfig, GainControl, MAX_LNA_GAIN, MAX_VGA_GAIN};
+
+// Same code clap exits with for a malformed command line
+pub const EXIT_BAD_CONFIG: u8 = 2;
+pub const EXIT_NO_DEVICE: u8 = 3;
+/// The scan ran and wrote its report, but heard nothing.
+pub const EXIT_NO_DETECTIONS: u8 = 4;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Scans for TETRA carriers with a HackRF One")]
+pub struct Cli {
+    /// Without a command, scans as `config.json` says (`instant_scan`)
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Sweep the bands once or for a set time
+    #[command(subcommand)]
+    Scan(ScanCommand),
+    /// Sweep until stopped (or for --duration), rewriting the report after
+    /// every pass
+    Monitor(ScanArgs),
+    /// Sweep a recorded capture instead of the radio
+    #[command(subcommand)]
+    Replay(ReplayCommand),
+    /// Show the HackRF attached, if any
+    Devices,
+    /// Print the JSON Schema of the scan report
+    Schema,
+    /// Check an existing report against the schema
+    Validate { file: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ScanCommand {
+    /// One sweep over every band
+    Instant(ScanArgs),
+    /// Repeated sweeps for `scan_duration` seconds after a countdown
+    Scheduled(ScanArgs),
+}
+
+/// The same modes as `scan`, each tune starting the capture over.
+#[derive(Subcommand, Debug)]
+pub enum ReplayCommand {
+    /// One sweep over every band
+    Instant(ReplayArgs),
+    /// Repeated sweeps for `scan_duration` seconds after a countdown
+    Scheduled(ReplayArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// Interleaved signed 8-bit I/Q, as written by hackrf_transfer
+    pub file: String,
+    /// Frequency the capture was tuned to, in Hz
+    #[arg(long)]
+    pub center_freq: Option<u64>,
+    /// Rate the capture was recorded at, in samples per second
+    #[arg(long)]
+    pub sample_rate: Option<u32>,
+    #[command(flatten)]
+    pub args: ScanArgs,
+}
+
+/// Options shared by every scan. Anything given here wins over the config
+/// file.
+#[derive(Args, Debug, Default)]
+pub struct ScanArgs {
+    #[arg(long, default_value = "config.json")]
+    pub config: String,
+    /// Where to write the report instead of the mode's usual file
+    #[arg(long)]
+    pub output: Option<String>,
+    /// Band preset to scan in place of the configured bands; repeatable
+    #[arg(long = "band")]
+    pub bands: Vec<String>,
+    /// Scan length in seconds, for scheduled scans and monitoring; an
+    /// instant scan refuses it
+    #[arg(long)]
+    pub duration: Option<u64>,
+    /// Let the gain follow the signal instead of fixed LNA/VGA settings
+    #[arg(long, conflicts_with_all = ["lna", "vga"])]
+    pub auto_gain: bool,
+    /// LNA gain in dB, applied in steps of 8
+    #[arg(long, value_parser = clap::value_parser!(u16).range(0..=MAX_LNA_GAIN as i64))]
+    pub lna: Option<u16>,
+    /// VGA gain in dB, applied in steps of 2
+    #[arg(long, value_parser = clap::value_parser!(u16).range(0..=MAX_VGA_GAIN as i64))]
+    pub vga: Option<u16>,
+    /// RF amplifier on or off
+    #[arg(long)]
+    pub amp: Option<bool>,
+}
+
+impl ScanArgs {
+    pub fn overrides_gain(&self) -> bool {
+        self.auto_gain || self.lna.is_some() || self.vga.is_some() || self.amp.is_some()
+    }
+
+    /// Applies the command line on top of the config file.
+    pub fn apply(&self, config: &mut Config) {
+        if !self.bands.is_empty() {
+            config.bands = self.bands.iter().cloned().map(BandSpec::Preset).collect();
+        }
+        if let Some(duration) = self.duration {
+            config.scan_duration = duration;
+        }
+        if !self.overrides_gain() {
+            return;
+        }
+
+        // Whatever the command line leaves out comes from where the config
+        // would have started
+        let start = GainControl::new(config.gain).gains();
+        let amp = self.amp.unwrap_or(start.amp);
+        let auto = self.auto_gain
+            || (matches!(config.gain, GainConfig::Auto { .. })
+                && self.lna.is_none()
+                && self.vga.is_none());
+        config.gain = if auto {
+            GainConfig::Auto { amp }
+        } else {
+            GainConfig::Manual {
+                amp,
+                lna: self.lna.unwrap_or(start.lna),
+                vga: self.vga.unwrap_or(start.vga),
+            }
+        };
+    }
+}
@@ -0,0 +1,246 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+
+use crate::band::{default_bands, Band, BandSpec};
+use crate::detect::DetectionConfig;
+use crate::error::ScanError;
+use crate::gain::{GainConfig, LNA_GAIN_STEP, MAX_LNA_GAIN, MAX_VGA_GAIN};
+use crate::source::{
+    HACKRF_MAX_FREQ, HACKRF_MAX_SAMPLE_RATE, HACKRF_MIN_FREQ, HACKRF_MIN_SAMPLE_RATE,
+};
+
+fn default_start_after_duration() -> u64 {
+    5
+}
+
+fn default_scan_duration() -> u64 {
+    120
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Config {
+    #[serde(default)]
+    pub instant_scan: bool,
+    /// Countdown before a scheduled scan, in seconds
+    #[serde(default = "default_start_after_duration")]
+    pub start_after_duration: u64,
+    /// Length of a scheduled scan, in seconds
+    #[serde(default = "default_scan_duration")]
+    pub scan_duration: u64,
+    #[serde(default)]
+    pub replay: Option<ReplayConfig>,
+    #[serde(default)]
+    pub detection: DetectionConfig,
+    #[serde(default = "default_bands")]
+    pub bands: Vec<BandSpec>,
+    #[serde(default)]
+    pub gain: GainConfig,
+}
+
+// Scan a recorded capture instead of a live HackRF
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct ReplayConfig {
+    pub path: String,
+    pub center_freq: u64,
+    pub sample_rate: u32,
+}
+
+pub fn load_config(config_path: &str) -> Result<Config, ScanError> {
+    let file = File::open(config_path)
+        .map_err(|e| ScanError::Config(format!("{}: {}", config_path, e)))?;
+    let reader = std::io::BufReader::new(file);
+    let mut json = serde_json::Deserializer::from_reader(reader);
+    // Name the field that failed rather than just the line it was on
+    serde_path_to_error::deserialize(&mut json).map_err(|e| {
+        let at = e.path().to_string();
+        ScanError::Config(format!("{}: {}: {}", config_path, at, e.into_inner()))
+    })
+}
+
+/// Checks the config and the band plan resolved from it against what the
+/// HackRF can do and against each other. Every problem found is listed,
+/// one per line, naming the field and what it may be.
+pub fn validate(config: &Config, bands: &[Band]) -> Result<(), ScanError> {
+    let mut problems = Vec::new();
+
+    if config.scan_duration == 0 {
+        problems.push("scan_duration: must be at least 1 second".to_string());
+    }
+    check_detection(&config.detection, &mut problems);
+    check_gain("gain", &config.gain, &mut problems);
+
+    if let Some(replay) = &config.replay {
+        check_freq("replay.center_freq", replay.center_freq, &mut problems);
+        check_sample_rate("replay.sample_rate", replay.sample_rate, &mut problems);
+    }
+
+    if bands.is_empty() {
+        problems.push("bands: at least one band is needed".to_string());
+    }
+    for (i, band) in bands.iter().enumerate() {
+        let field = format!("bands[{}] ({})", i, band.name);
+        check_band(&field, band, &mut problems);
+        if let Some(replay) = &config.replay {
+            check_band_in_capture(&field, band, replay, &mut problems);
+        }
+
+        for (j, other) in bands.iter().enumerate().skip(i + 1) {
+            if other.name == band.name {
+                problems.push(format!(
+                    "bands[{}]: name '{}' is already used by bands[{}]",
+                    j, other.name, i
+                ));
+            }
+            // Scanning the same channels twice would only double count them
+            if other.link == band.link
+                && other.start_freq < band.end_freq
+                && band.start_freq < other.end_freq
+            {
+                problems.push(format!(
+                    "bands[{}] ({}): overlaps bands[{}] ({}) on the same link",
+                    j, other.name, i, band.name
+                ));
+            }
+        }
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err(ScanError::Config(problems.remove(0))),
+        n => Err(ScanError::Config(format!(
+            "{} problems\n  {}",
+            n,
+            problems.join("\n  ")
+        ))),
+    }
+}
+
+fn check_freq(field: &str, freq: u64, problems: &mut Vec<String>) {
+    if !(HACKRF_MIN_FREQ..=HACKRF_MAX_FREQ).contains(&freq) {
+        problems.push(format!(
+            "{}: {} Hz is outside the HackRF's {} to {} Hz",
+            field, freq, HACKRF_MIN_FREQ, HACKRF_MAX_FREQ
+        ));
+    }
+}
+
+fn check_sample_rate(field: &str, sample_rate: u32, problems: &mut Vec<String>) {
+    if !(HACKRF_MIN_SAMPLE_RATE..=HACKRF_MAX_SAMPLE_RATE).contains(&sample_rate) {
+        problems.push(format!(
+            "{}: {} samples/s is outside the HackRF's {} to {} samples/s",
+            field, sample_rate, HACKRF_MIN_SAMPLE_RATE, HACKRF_MAX_SAMPLE_RATE
+        ));
+    }
+}
+
+fn check_gain(field: &str, gain: &GainConfig, problems: &mut Vec<String>) {
+    let GainConfig::Manual { lna, vga, .. } = *gain else {
+        return;
+    };
+    if lna > MAX_LNA_GAIN || lna % LNA_GAIN_STEP != 0 {
+        problems.push(format!(
+            "{}.lna: {} dB must be 0 to {} dB in steps of {}",
+            field, lna, MAX_LNA_GAIN, LNA_GAIN_STEP
+        ));
+    }
+    if vga > MAX_VGA_GAIN || vga % 2 != 0 {
+        problems.push(format!(
+            "{}.vga: {} dB must be 0 to {} dB in steps of 2",
+            field, vga, MAX_VGA_GAIN
+        ));
+    }
+}
+
+fn check_detection(detection: &DetectionConfig, problems: &mut Vec<String>) {
+    if detection.snr_margin_db.is_nan() || detection.snr_margin_db <= 0.0 {
+        problems.push(format!(
+            "detection.snr_margin_db: {} must be above 0 dB",
+            detection.snr_margin_db
+        ));
+    }
+    if !(0.0..=100.0).contains(&detection.noise_percentile) {
+        problems.push(format!(
+            "detection.noise_percentile: {} must be 0 to 100",
+            detection.noise_percentile
+        ));
+    }
+    if detection.cfar_window > 0 && detection.cfar_guard >= detection.cfar_window {
+        problems.push(format!(
+            "detection.cfar_guard: {} leaves no neighbours in a window of {}; must be below it",
+            detection.cfar_guard, detection.cfar_window
+        ));
+    }
+}
+
+fn check_band(field: &str, band: &Band, problems: &mut Vec<String>) {
+    check_freq(&format!("{}.start_freq", field), band.start_freq, problems);
+    check_freq(&format!("{}.end_freq", field), band.end_freq, problems);
+    check_sample_rate(
+        &format!("{}.sample_rate", field),
+        band.sample_rate,
+        problems,
+    );
+    if let Some(gain) = &band.gain {
+        check_gain(&format!("{}.gain", field), gain, problems);
+    }
+
+    if band.start_freq >= band.end_freq {
+        problems.push(format!(
+            "{}.end_freq: {} Hz must be above start_freq ({} Hz)",
+            field, band.end_freq, band.start_freq
+        ));
+    }
+    if band.dwell_ms == 0 {
+        problems.push(format!("{}.dwell_ms: must be at least 1 ms", field));
+    }
+    if band.raster == 0 {
+        problems.push(format!("{}.raster: must be at least 1 Hz", field));
+        return;
+    }
+    if band.end_freq.saturating_sub(band.start_freq) < band.raster {
+        problems.push(format!(
+            "{}: {} Hz wide, narrower than one {} Hz raster channel",
+            field,
+            band.end_freq.saturating_sub(band.start_freq),
+            band.raster
+        ));
+    }
+    // Each tuning holds at least two channels, one either side of the DC spike
+    if band.raster * 2 > band.usable_bandwidth() {
+        problems.push(format!(
+            "{}.raster: {} Hz must be at most {} Hz, half the usable bandwidth at {} samples/s",
+            field,
+            band.raster,
+            band.usable_bandwidth() / 2,
+            band.sample_rate
+        ));
+    }
+}
+
+/// A replayed capture can only be cut down by whole-number decimation and
+/// only holds the spectrum it was recorded with.
+fn check_band_in_capture(
+    field: &str,
+    band: &Band,
+    replay: &ReplayConfig,
+    problems: &mut Vec<String>,
+) {
+    if band.sample_rate == 0 || !replay.sample_rate.is_multiple_of(band.sample_rate) {
+        problems.push(format!(
+            "{}.sample_rate: {} samples/s must divide the capture's {} samples/s",
+            field, band.sample_rate, replay.sample_rate
+        ));
+    }
+    let half = replay.sample_rate as u64 / 2;
+    let (low, high) = (
+        replay.center_freq.saturating_sub(half),
+        replay.center_freq + half,
+    );
+    if band.end_freq <= low || band.start_freq >= high {
+        problems.push(format!(
+            "{}: {} to {} Hz lies outside the capture's {} to {} Hz",
+            field, band.start_freq, band.end_freq, low, high
+        ));
+    }
+}
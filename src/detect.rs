@@ -0,0 +1,84 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::spectrum::ChannelPower;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct DetectionConfig {
+    /// How far above the local noise floor a channel must be to count
+    pub snr_margin_db: f64,
+    /// Percentile of channel powers taken as the noise floor (50 = median)
+    pub noise_percentile: f64,
+    /// Channels either side of the one under test used to estimate its floor
+    pub cfar_window: usize,
+    /// Channels right next to the one under test that are left out of the
+    /// estimate so a wide signal does not raise its own floor
+    pub cfar_guard: usize,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        DetectionConfig {
+            snr_margin_db: 10.0,
+            noise_percentile: 50.0,
+            cfar_window: 8,
+            cfar_guard: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Detection {
+    pub channel: ChannelPower,
+    pub noise_floor_dbfs: f64,
+    pub snr_db: f64,
+}
+
+pub fn percentile(values: &mut [f64], percentile: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * (values.len() - 1) as f64).round();
+    Some(values[rank as usize])
+}
+
+/// Noise floor across every channel of one tuning step. Each step is
+/// captured at its own time and gain and analysed as soon as it is in, so
+/// the floor is taken per step rather than over the whole band.
+pub fn step_noise_floor(channels: &[ChannelPower], config: &DetectionConfig) -> Option<f64> {
+    let mut powers: Vec<f64> = channels.iter().map(|c| c.power_dbfs).collect();
+    percentile(&mut powers, config.noise_percentile)
+}
+
+/// Order-statistic CFAR: each channel is compared against the percentile of
+/// its neighbours rather than a fixed level, falling back to the step floor
+/// when there are no neighbours to learn from.
+pub fn cfar_detect(channels: &[ChannelPower], config: &DetectionConfig) -> Vec<Detection> {
+    let Some(step_floor) = step_noise_floor(channels, config) else {
+        return Vec::new();
+    };
+
+    let mut detections = Vec::new();
+    for (index, channel) in channels.iter().enumerate() {
+        let low = index.saturating_sub(config.cfar_window);
+        let high = (index + config.cfar_window).min(channels.len() - 1);
+        let mut training: Vec<f64> = (low..=high)
+            .filter(|&i| i.abs_diff(index) > config.cfar_guard)
+            .map(|i| channels[i].power_dbfs)
+            .collect();
+        let noise_floor_dbfs =
+            percentile(&mut training, config.noise_percentile).unwrap_or(step_floor);
+
+        let snr_db = channel.power_dbfs - noise_floor_dbfs;
+        if snr_db >= config.snr_margin_db {
+            detections.push(Detection {
+                channel: *channel,
+                noise_floor_dbfs,
+                snr_db,
+            });
+        }
+    }
+    detections
+}
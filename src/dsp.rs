@@ -0,0 +1,105 @@
+use num_complex::Complex;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+// Anything quieter than this is reported as the floor instead of -inf
+const MIN_POWER: f64 = 1e-12;
+
+/// Interleaved I/Q takes one byte for each of I and Q.
+pub const BYTES_PER_SAMPLE: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PowerStats {
+    /// Mean sample magnitude
+    pub mean_dbfs: f64,
+    /// Strongest single sample
+    pub peak_dbfs: f64,
+    /// Average power (I² + Q²)
+    pub rms_dbfs: f64,
+}
+
+/// Decodes interleaved signed 8-bit I/Q into complex samples scaled so that
+/// full scale has a magnitude of 1.0.
+pub fn decode_iq(samples: &[u8]) -> Vec<Complex<f32>> {
+    samples
+        .chunks_exact(BYTES_PER_SAMPLE)
+        .map(iq_sample)
+        .collect()
+}
+
+/// Decodes one I/Q byte pair the way `decode_iq` does.
+pub fn iq_sample(pair: &[u8]) -> Complex<f32> {
+    Complex::new(pair[0] as i8 as f32 / 128.0, pair[1] as i8 as f32 / 128.0)
+}
+
+/// Bytes of I/Q that `duration` of samples at `sample_rate` takes up.
+pub fn iq_bytes(sample_rate: u32, duration: Duration) -> usize {
+    (sample_rate as f64 * duration.as_secs_f64()) as usize * BYTES_PER_SAMPLE
+}
+
+/// Hamming-windowed sinc low-pass of `len` taps, `cutoff` a fraction of
+/// the sample rate, scaled for unity gain at DC.
+pub fn lowpass_taps(len: usize, cutoff: f64) -> Vec<f64> {
+    let middle = (len - 1) as f64 / 2.0;
+    let mut taps: Vec<f64> = (0..len)
+        .map(|n| {
+            let x = n as f64 - middle;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let window = 0.54 - 0.46 * (2.0 * PI * n as f64 / (len - 1) as f64).cos();
+            sinc * window
+        })
+        .collect();
+    let gain: f64 = taps.iter().sum();
+    taps.iter_mut().for_each(|t| *t /= gain);
+    taps
+}
+
+pub fn power_to_dbfs(power: f64) -> f64 {
+    10.0 * power.max(MIN_POWER).log10()
+}
+
+pub fn analyze_samples(samples: &[Complex<f32>]) -> Option<PowerStats> {
+    let mut power = PowerAccumulator::default();
+    power.add(samples);
+    power.stats()
+}
+
+/// `analyze_samples` kept up block by block, so a dwell of any length
+/// needs no more than its running sums.
+#[derive(Default)]
+pub struct PowerAccumulator {
+    magnitude_sum: f64,
+    power_sum: f64,
+    peak_power: f64,
+    count: usize,
+}
+
+impl PowerAccumulator {
+    pub fn add(&mut self, samples: &[Complex<f32>]) {
+        for sample in samples {
+            let power = sample.norm_sqr() as f64;
+            self.magnitude_sum += power.sqrt();
+            self.power_sum += power;
+            self.peak_power = self.peak_power.max(power);
+        }
+        self.count += samples.len();
+    }
+
+    pub fn stats(&self) -> Option<PowerStats> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let count = self.count as f64;
+        let mean_magnitude = self.magnitude_sum / count;
+        Some(PowerStats {
+            mean_dbfs: power_to_dbfs(mean_magnitude * mean_magnitude),
+            peak_dbfs: power_to_dbfs(self.peak_power),
+            rms_dbfs: power_to_dbfs(self.power_sum / count),
+        })
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+
+use crate::cli::{EXIT_BAD_CONFIG, EXIT_NO_DEVICE};
+
+/// Everything that can stop a scan, or a single tuning within one.
+#[derive(Debug)]
+pub enum ScanError {
+    /// No HackRF is attached, or it would not open
+    DeviceNotFound,
+    /// A transfer to or from the device failed
+    Usb(String),
+    /// The device refused a frequency, sample rate or gain setting
+    Tuning(String),
+    /// The config file or command line asks for something impossible
+    Config(String),
+    /// Reading a capture or writing a report failed
+    Io(io::Error),
+    /// A report could not be produced or read back as JSON
+    Report(String),
+}
+
+impl ScanError {
+    /// Whether the failure is likely to clear up on its own, so the
+    /// tuning is worth retrying and the scan worth continuing.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ScanError::Usb(_) | ScanError::Tuning(_))
+    }
+
+    /// Whether the device has dropped off the bus or stopped answering, as
+    /// opposed to refusing one setting.
+    pub fn is_device_lost(&self) -> bool {
+        matches!(self, ScanError::DeviceNotFound | ScanError::Usb(_))
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            ScanError::Config(_) => ExitCode::from(EXIT_BAD_CONFIG),
+            ScanError::DeviceNotFound => ExitCode::from(EXIT_NO_DEVICE),
+            _ => ExitCode::FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanError::DeviceNotFound => write!(f, "No HackRF found"),
+            ScanError::Usb(reason) => write!(f, "USB transfer failed: {}", reason),
+            ScanError::Tuning(reason) => write!(f, "Tuning failed: {}", reason),
+            ScanError::Config(reason) => write!(f, "Bad config: {}", reason),
+            ScanError::Io(error) => write!(f, "{}", error),
+            ScanError::Report(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScanError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ScanError {
+    fn from(error: io::Error) -> Self {
+        ScanError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ScanError {
+    fn from(error: serde_json::Error) -> Self {
+        ScanError::Report(error.to_string())
+    }
+}
@@ -0,0 +1,121 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::dsp::{analyze_samples, decode_iq, PowerStats};
+use crate::error::ScanError;
+use crate::source::{Gains, SdrSource};
+
+pub const MAX_LNA_GAIN: u16 = 40;
+pub const LNA_GAIN_STEP: u16 = 8;
+pub const MAX_VGA_GAIN: u16 = 62;
+
+// A peak this close to full scale means the ADC is about to clip
+const CLIP_DBFS: f64 = -1.0;
+// Below this the noise only toggles the bottom couple of ADC bits
+const QUANTISATION_FLOOR_DBFS: f64 = -36.0;
+// VGA change per automatic adjustment
+const AUTO_VGA_STEP: u16 = 6;
+const AUTO_GAIN_ATTEMPTS: usize = 8;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum GainConfig {
+    Manual {
+        amp: bool,
+        lna: u16,
+        vga: u16,
+    },
+    /// Starts from the manual defaults and walks LNA/VGA on every step. The
+    /// RF amp is left as configured to spare its relay.
+    Auto {
+        amp: bool,
+    },
+}
+
+impl Default for GainConfig {
+    fn default() -> Self {
+        GainConfig::Manual {
+            amp: true,
+            lna: 24,
+            vga: 28,
+        }
+    }
+}
+
+/// Gains for one band, carried from step to step so automatic mode starts
+/// each tuning where the last one settled.
+pub struct GainControl {
+    auto: bool,
+    current: Gains,
+}
+
+impl GainControl {
+    pub fn new(config: GainConfig) -> Self {
+        let (auto, current) = match config {
+            GainConfig::Manual { amp, lna, vga } => (false, Gains { amp, lna, vga }),
+            GainConfig::Auto { amp } => (
+                true,
+                Gains {
+                    amp,
+                    lna: 24,
+                    vga: 28,
+                },
+            ),
+        };
+        GainControl { auto, current }
+    }
+
+    pub fn gains(&self) -> Gains {
+        self.current
+    }
+
+    /// Moves one notch away from clipping or from the quantisation floor.
+    /// Returns false once the probe sits comfortably between the two.
+    fn adjust(&mut self, probe: &PowerStats) -> bool {
+        let gains = &mut self.current;
+        if probe.peak_dbfs > CLIP_DBFS {
+            // Back the LNA off first so the mixer is not overdriven either
+            if gains.lna > 16 {
+                gains.lna -= LNA_GAIN_STEP;
+            } else if gains.vga > 0 {
+                gains.vga = gains.vga.saturating_sub(AUTO_VGA_STEP);
+            } else if gains.lna > 0 {
+                gains.lna -= LNA_GAIN_STEP;
+            } else {
+                return false;
+            }
+            true
+        } else if probe.rms_dbfs < QUANTISATION_FLOOR_DBFS {
+            if gains.lna < MAX_LNA_GAIN {
+                gains.lna += LNA_GAIN_STEP;
+            } else if gains.vga < MAX_VGA_GAIN {
+                gains.vga = (gains.vga + AUTO_VGA_STEP).min(MAX_VGA_GAIN);
+            } else {
+                return false;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// In automatic mode, probes the freshly tuned source and nudges its
+    /// gains until they settle.
+    pub fn settle(&mut self, source: &mut dyn SdrSource) -> Result<Gains, ScanError> {
+        if !self.auto || !source.supports_gain() {
+            return Ok(self.current);
+        }
+
+        for _ in 0..AUTO_GAIN_ATTEMPTS {
+            let probe = decode_iq(&source.read_block()?);
+            let Some(stats) = analyze_samples(&probe) else {
+                break;
+            };
+            if !self.adjust(&stats) {
+                break;
+            }
+            source.set_gains(self.current)?;
+        }
+        Ok(self.current)
+    }
+}
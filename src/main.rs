@@ -1,224 +1,261 @@
-use hackrfone::{HackRfOne, UnknownMode};
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
+mod activity;
+mod analysis;
+mod band;
+mod bsch;
+mod burst;
+mod carrier;
+mod cli;
+mod coding;
+mod config;
+mod demod;
+mod detect;
+mod dsp;
+mod error;
+mod gain;
+mod pipeline;
+mod replay;
+mod report;
+mod schema;
+mod shutdown;
+mod slots;
+mod source;
+mod spectrum;
+mod sysinfo;
+mod uplink;
+
+use clap::Parser;
+use std::process::ExitCode;
 use std::time::{Duration, Instant};
 
-#[derive(Serialize, Deserialize)]
-struct SignalData {
-    is_signal: String,
-    signal_strength: Option<f64>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-struct Config {
-    instant_scan: bool,
-    start_after_duration: u64,
-    scan_duration: u64,
-}
+use band::{resolve_bands, Band};
+use cli::{Cli, Command, ReplayArgs, ReplayCommand, ScanArgs, ScanCommand, EXIT_NO_DETECTIONS};
+use config::{load_config, validate as validate_config, Config, ReplayConfig};
+use error::ScanError;
+use replay::ReplaySource;
+use report::{ScanMode, ScanReport, SCHEMA_VERSION};
+use schema::{report_schema, validate_report};
+use shutdown::Shutdown;
+use source::{find_hackrf, HackRfSource, SdrSource};
 
-fn load_config(config_path: &str) -> Result<Config, Box<dyn std::error::Error>> {
-    let file = File::open(config_path)?;
-    let reader = std::io::BufReader::new(file);
-    let config = serde_json::from_reader(reader)?;
-    Ok(config)
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            error.exit_code()
+        }
+    }
 }
 
-fn scan_freq(
-    mut radio: HackRfOne<UnknownMode>,
-    frequency: u64,
-    sample_rate: u32,
-    duration: Duration,
-) -> Vec<u8> {
-    radio.set_freq(frequency).expect("Failed to set frequency");
-    radio
-        .set_sample_rate(sample_rate, 1)
-        .expect("Failed to set sample rate");
-    radio
-        .set_amp_enable(true)
-        .expect("Failed to enable amplifier");
-    radio.set_lna_gain(24).expect("Failed to set LNA gain");
-    radio.set_vga_gain(28).expect("Failed to set VGA gain");
-
-    // Enter RX mode and receive samples
-    let mut radio_rx = radio.into_rx_mode().expect("Failed to enter RX mode");
-
-    let start_time = Instant::now();
-    let mut raw_samples = Vec::new();
-
-    loop {
-        let samples = radio_rx.rx().expect("Failed to receive samples");
-        raw_samples.extend(samples);
-
-        if start_time.elapsed() >= duration {
-            break;
+async fn run(cli: Cli) -> Result<ExitCode, ScanError> {
+    match cli.command {
+        None => {
+            let args = ScanArgs {
+                config: "config.json".to_string(),
+                ..Default::default()
+            };
+            scan(&args, None, None).await
+        }
+        Some(Command::Scan(ScanCommand::Instant(args))) => {
+            scan(&args, Some(ScanMode::Instant), None).await
+        }
+        Some(Command::Scan(ScanCommand::Scheduled(args))) => {
+            scan(&args, Some(ScanMode::Scheduled), None).await
+        }
+        Some(Command::Monitor(args)) => scan(&args, Some(ScanMode::Monitor), None).await,
+        Some(Command::Replay(ReplayCommand::Instant(replay))) => {
+            replay_capture(replay, ScanMode::Instant).await
+        }
+        Some(Command::Replay(ReplayCommand::Scheduled(replay))) => {
+            replay_capture(replay, ScanMode::Scheduled).await
         }
+        Some(Command::Devices) => devices(),
+        Some(Command::Schema) => {
+            let schema = serde_json::to_string_pretty(&report_schema())?;
+            println!("{}", schema);
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Validate { file }) => validate(&file),
     }
-
-    raw_samples
 }
 
-fn analyze_samples(samples: Vec<u8>) -> Vec<f64> {
-    samples
-        .iter()
-        .map(|&sample| {
-            let sample_f64 = sample as f64;
-            if sample_f64 > 0.0 {
-                20.0 * sample_f64.log10()
-            } else {
-                0.0
-            }
-        })
-        .collect()
+async fn replay_capture(replay: ReplayArgs, mode: ScanMode) -> Result<ExitCode, ScanError> {
+    let capture = (replay.file, replay.center_freq, replay.sample_rate);
+    scan(&replay.args, Some(mode), Some(capture)).await
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = load_config("config.json")?;
-
-    if config.instant_scan {
-        run_instant_scan().await?;
-    } else {
-        run_scan_over_duration(config.start_after_duration, config.scan_duration).await?;
+/// Runs a scan in `mode`, or the one the config file picks when there is
+/// none. `replay` is a capture to scan in place of the radio, with its
+/// centre frequency and sample rate if given on the command line.
+async fn scan(
+    args: &ScanArgs,
+    mode: Option<ScanMode>,
+    replay: Option<(String, Option<u64>, Option<u32>)>,
+) -> Result<ExitCode, ScanError> {
+    let mut config = load_config(&args.config)?;
+    args.apply(&mut config);
+    if let Some((path, center_freq, sample_rate)) = replay {
+        let configured = config.replay.as_ref();
+        let center_freq = center_freq.or(configured.map(|r| r.center_freq));
+        let sample_rate = sample_rate.or(configured.map(|r| r.sample_rate));
+        let (Some(center_freq), Some(sample_rate)) = (center_freq, sample_rate) else {
+            return Err(ScanError::Config(
+                "replay needs --center-freq and --sample-rate, or a replay section in the config"
+                    .to_string(),
+            ));
+        };
+        config.replay = Some(ReplayConfig {
+            path,
+            center_freq,
+            sample_rate,
+        });
     }
 
-    Ok(())
-}
-
-async fn run_instant_scan() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Running instant scan...");
-
-    let mut results: HashMap<String, Value> = HashMap::new();
-    let mut count = 1;
-
-    let start_freq = 380_000_000u64;
-    let end_freq = 420_000_000u64;
-    let step = 1_000_000u64;
-    let sample_rate = 1_000_000u32;
-    let duration = Duration::from_secs(1);
-
-    for freq in (start_freq..=end_freq).step_by(step as usize) {
-        let radio = HackRfOne::new().expect("Failed to open HackRF One");
-        let raw_samples = scan_freq(radio, freq, sample_rate, duration);
-
-        println!("Scanning frequency: {} MHz", freq as f64 / 1_000_000.0);
-        println!("Received {} samples", raw_samples.len());
-
-        let signal_strengths_db = analyze_samples((raw_samples).clone());
-        let max_strength = signal_strengths_db
-            .iter()
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .copied();
-
-        if let Some(max) = max_strength {
-            if max > 49.0 {
-                println!("Signal detected: true");
-                results.insert(
-                    count.to_string(),
-                    json!({
-                        "freq": freq as f64 / 1_000_000.0,
-                        "strength": max,
-                        "sample_count": raw_samples.len(),
-                        "tetra_durations": "none"
-                    }),
-                );
-                count += 1;
-            } else {
-                println!(
-                    "Signal below threshold detected at {} MHz with strength {:.2} dB",
-                    freq as f64 / 1_000_000.0,
-                    max
-                );
-            }
-        } else {
-            println!("No signal detected.");
+    let mut bands = resolve_bands(&config.bands).map_err(ScanError::Config)?;
+    if args.overrides_gain() {
+        // The command line wins over per-band gains in the file too
+        for band in &mut bands {
+            band.gain = None;
         }
     }
-
-    if results.is_empty() {
-        results.insert(
-            "1".to_string(),
-            json!({"freq": 0, "strength": 0, "sample_count": 0, "tetra_durations":"none"}),
-        );
+    validate_config(&config, &bands)?;
+    let mode = mode.unwrap_or(if config.instant_scan {
+        ScanMode::Instant
+    } else {
+        ScanMode::Scheduled
+    });
+    if mode == ScanMode::Instant && args.duration.is_some() {
+        return Err(ScanError::Config(
+            "--duration has no effect on an instant scan, which sweeps once".to_string(),
+        ));
     }
 
-    let json = serde_json::to_string_pretty(&results)?;
-    println!("{}", json);
+    let source: Box<dyn SdrSource> = match &config.replay {
+        Some(replay) => {
+            println!("Replaying capture {}", replay.path);
+            Box::new(ReplaySource::new(
+                &replay.path,
+                replay.center_freq,
+                replay.sample_rate,
+            ))
+        }
+        None => {
+            find_hackrf().ok_or(ScanError::DeviceNotFound)?;
+            Box::new(HackRfSource::default())
+        }
+    };
+
+    let output = args.output.as_deref().unwrap_or(match mode {
+        ScanMode::Instant => "tetra_instantdata.json",
+        ScanMode::Scheduled | ScanMode::Monitor => "tetra_scheduledata.json",
+    });
+    let shutdown = Shutdown::listen();
+    let report = match mode {
+        ScanMode::Instant => run_instant_scan(source, &config, &bands, output, &shutdown).await?,
+        ScanMode::Scheduled => {
+            let length = Duration::from_secs(config.scan_duration);
+            run_scan_over_duration(
+                source,
+                &config,
+                &bands,
+                output,
+                mode,
+                Some(length),
+                &shutdown,
+            )
+            .await?
+        }
+        ScanMode::Monitor => {
+            let length = args.duration.map(Duration::from_secs);
+            run_scan_over_duration(source, &config, &bands, output, mode, length, &shutdown).await?
+        }
+    };
 
-    let mut file = File::create("tetra_instantdata.json")?;
-    file.write_all(json.as_bytes())?;
+    if report.channels.is_empty() {
+        println!("Nothing detected");
+        return Ok(ExitCode::from(EXIT_NO_DETECTIONS));
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-    Ok(())
+fn devices() -> Result<ExitCode, ScanError> {
+    let device = find_hackrf().ok_or(ScanError::DeviceNotFound)?;
+    println!(
+        "HackRF One: board id {}, firmware {}",
+        device
+            .board_id
+            .map_or("unknown".to_string(), |id| id.to_string()),
+        device.firmware_version.as_deref().unwrap_or("unknown")
+    );
+    Ok(ExitCode::SUCCESS)
 }
 
-async fn run_scan_over_duration(start_after_duration: u64, scan_duration: u64) -> Result<(), Box<dyn std::error::Error>> {
-    for i in (1..=start_after_duration).rev() {
-        println!("Scan starts in {} seconds", i);
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+fn validate(path: &str) -> Result<ExitCode, ScanError> {
+    let problems = validate_report(path)?;
+    if problems.is_empty() {
+        println!("{} matches schema version {}", path, SCHEMA_VERSION);
+        return Ok(ExitCode::SUCCESS);
     }
 
-    println!("Starting scan for {} seconds...", scan_duration);
-    let scan_start_time = Instant::now();
-
-    let start_freq = 380_000_000u64;
-    let end_freq = 420_000_000u64;
-    let step = 1_000_000u64;
-    let sample_rate = 1_000_000u32;
-    let duration_per_freq = Duration::from_secs(1);
-
-    // Initialize an empty vector to store frequency data
-    let mut freq_data_vec: Vec<Value> = vec![];
-    let mut freq_id_map: HashMap<u64, usize> = HashMap::new(); // Maps frequency to ID
+    for problem in &problems {
+        println!("  {}", problem);
+    }
+    Err(ScanError::Report(format!(
+        "{} is not compatible with schema version {} ({} problems)",
+        path,
+        SCHEMA_VERSION,
+        problems.len()
+    )))
+}
 
-    while Instant::now().duration_since(scan_start_time) < Duration::from_secs(scan_duration) {
-        for freq in (start_freq..=end_freq).step_by(step as usize) {
-            if Instant::now().duration_since(scan_start_time) >= Duration::from_secs(scan_duration) {
-                break; // End of the duration scan
-            }
+async fn run_instant_scan(
+    source: Box<dyn SdrSource>,
+    config: &Config,
+    bands: &[Band],
+    output: &str,
+    shutdown: &Shutdown,
+) -> Result<ScanReport, ScanError> {
+    println!("Running instant scan...");
+    let sweep_start = Instant::now();
+    let report = pipeline::run(
+        source,
+        config,
+        bands,
+        ScanMode::Instant,
+        None,
+        output,
+        shutdown,
+    )
+    .await;
+    println!("Sweep took {:.1} s", sweep_start.elapsed().as_secs_f64());
+    report
+}
 
-            let radio = HackRfOne::new().expect("Failed to open HackRF One");
-            let raw_samples = scan_freq(radio, freq, sample_rate, duration_per_freq);
-            let signal_strengths_db = analyze_samples(raw_samples.clone());
-            let max_strength = signal_strengths_db.iter().max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)).copied();
-
-            if let Some(max) = max_strength {
-                if max > 49.0 {
-                    let current_time = Instant::now().duration_since(scan_start_time).as_secs();
-                    let freq_index = *freq_id_map.entry(freq).or_insert_with(|| {
-                        let new_index = freq_data_vec.len();
-                        freq_data_vec.push(json!({
-                            "freq": freq as f64 / 1_000_000.0,
-                            "strength": max,
-                            "sample_count": raw_samples.len(),
-                            "tetra_durations": ""
-
-                        }));
-                        new_index
-                    });
-
-                    let detection = &mut freq_data_vec[freq_index];
-                    let durations_str = detection["tetra_durations"].as_str().unwrap_or("");
-                    let new_duration = if durations_str.is_empty() {
-                        format!("{}-{}", current_time, current_time + 1)
-                    } else {
-                        format!("{},{}-{}", durations_str, current_time, current_time + 1)
-                    };
-
-                    detection["tetra_durations"] = json!(new_duration);
-                }
+/// Sweeps the bands over and over for `length`, or until stopped when
+/// there is none. Monitoring skips the countdown.
+async fn run_scan_over_duration(
+    source: Box<dyn SdrSource>,
+    config: &Config,
+    bands: &[Band],
+    output: &str,
+    mode: ScanMode,
+    length: Option<Duration>,
+    shutdown: &Shutdown,
+) -> Result<ScanReport, ScanError> {
+    if mode == ScanMode::Scheduled {
+        for i in (1..=config.start_after_duration).rev() {
+            if shutdown.requested() {
+                break;
             }
+            println!("Scan starts in {} seconds", i);
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         }
     }
 
-    // Organize results by frequency order
-    let results: Value = freq_data_vec.into_iter().enumerate().map(|(id, data)| (id.to_string(), data)).collect();
-
-    let json = serde_json::to_string_pretty(&results)?;
-    println!("{}", json);
-    let mut file = File::create("tetra_scheduledata.json")?;
-    file.write_all(json.as_bytes())?;
-
-    Ok(())
+    match length {
+        Some(length) => println!("Starting scan for {} seconds...", length.as_secs()),
+        None => println!("Monitoring until stopped..."),
+    }
+    pipeline::run(source, config, bands, mode, length, output, shutdown).await
 }
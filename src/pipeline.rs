@@ -0,0 +1,513 @@
+//! Capture and analysis run side by side. One blocking thread owns the
+//! radio and sweeps it, streaming each dwell's blocks through a bounded
+//! channel to a worker of its own, so the DSP on one tuning overlaps the
+//! capture of the next. Workers hand their findings to the scan, which
+//! merges them and writes the report.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+
+use crate::activity::{CoverageGap, ScanClock};
+use crate::analysis::{Analysis, DwellAnalysis, Findings, Tuning};
+use crate::band::{Band, Step};
+use crate::config::Config;
+use crate::detect::DetectionConfig;
+use crate::dsp::iq_bytes;
+use crate::error::ScanError;
+use crate::gain::{GainConfig, GainControl};
+use crate::report::{ScanMode, ScanReport};
+use crate::shutdown::Shutdown;
+use crate::source::{DeviceInfo, Gains, SdrSource};
+
+// Blocks of one dwell that may wait for its worker: 128 KiB transfers, so
+// about a second of samples at 8 Msps
+const DWELL_QUEUE_BLOCKS: usize = 128;
+// Results that may wait for the scan to merge them
+const EVENT_QUEUE: usize = 64;
+
+// Attempts at one tuning before it is skipped for the rest of the sweep
+const CAPTURE_ATTEMPTS: usize = 3;
+
+// Wait before the first reconnection attempt, doubling after each failure
+// up to the cap
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What the capture thread and the workers tell the scan.
+enum Event {
+    Analysed(Analysis),
+    /// Blocks of a dwell that arrived faster than its worker took them
+    Dropped {
+        freq: u64,
+        dropped: usize,
+        blocks: usize,
+    },
+    Gap(CoverageGap),
+    /// A pass over every band has been captured and analysed
+    SweepDone(DeviceInfo),
+}
+
+/// A fixed number of analysis workers, one per dwell in flight. Capture
+/// takes a free one before tuning, so there is always someone reading the
+/// blocks of the dwell being captured.
+struct Workers {
+    permits: Arc<Semaphore>,
+    count: u32,
+    runtime: Handle,
+    detection: Arc<DetectionConfig>,
+    clock: ScanClock,
+    origin: Instant,
+    events: mpsc::Sender<Event>,
+}
+
+/// Where the blocks of one dwell go, and how many of them did not make it.
+struct Dwell {
+    blocks: mpsc::Sender<Vec<u8>>,
+    realtime: bool,
+    sent: usize,
+    dropped: usize,
+    /// Set when the dwell is to be thrown away rather than reported
+    discarded: Arc<AtomicBool>,
+}
+
+/// A capture that failed, with whatever part of its dwell was read first.
+/// That part is analysed if the tuning is given up on, and thrown away if
+/// it is about to be captured again, so no tuning is reported twice.
+struct CaptureFailure {
+    error: ScanError,
+    partial: Option<Dwell>,
+}
+
+impl From<ScanError> for CaptureFailure {
+    fn from(error: ScanError) -> Self {
+        CaptureFailure {
+            error,
+            partial: None,
+        }
+    }
+}
+
+impl Dwell {
+    /// A live radio cannot be held up while the worker catches up, so a
+    /// block it has no room for is dropped and counted. A recording waits.
+    fn send(&mut self, block: Vec<u8>) {
+        self.sent += 1;
+        let delivered = if self.realtime {
+            self.blocks.try_send(block).is_ok()
+        } else {
+            self.blocks.blocking_send(block).is_ok()
+        };
+        if !delivered {
+            self.dropped += 1;
+        }
+    }
+}
+
+// Every core but the capture thread's, and never fewer than two so one
+// dwell can be analysed while the next is read
+fn worker_count() -> u32 {
+    std::thread::available_parallelism().map_or(2, |cores| cores.get().saturating_sub(1).max(2))
+        as u32
+}
+
+impl Workers {
+    /// Waits for a worker to be free.
+    fn reserve(&self) -> OwnedSemaphorePermit {
+        self.runtime
+            .block_on(self.permits.clone().acquire_owned())
+            .expect("the worker pool is never closed")
+    }
+
+    /// Sets the reserved worker analysing a dwell that starts now, block by
+    /// block as they are sent, and reporting once the sender is finished
+    /// with.
+    fn start(
+        &self,
+        worker: OwnedSemaphorePermit,
+        band: &Band,
+        step: &Step,
+        retune_latency: Duration,
+        gains: Option<Gains>,
+        realtime: bool,
+    ) -> Dwell {
+        let (sender, mut blocks) = mpsc::channel::<Vec<u8>>(DWELL_QUEUE_BLOCKS);
+        let (band, step) = (band.clone(), step.clone());
+        let (detection, clock, origin) = (self.detection.clone(), self.clock, self.origin);
+        let events = self.events.clone();
+        let discarded = Arc::new(AtomicBool::new(false));
+        let worker_discarded = discarded.clone();
+        let tuning = Tuning {
+            retune_latency,
+            gains,
+            started: Instant::now(),
+        };
+        self.runtime.spawn_blocking(move || {
+            let mut dwell = DwellAnalysis::new(&band, &step, &detection, &clock, origin, tuning);
+            while let Some(block) = blocks.blocking_recv() {
+                dwell.add(&block);
+            }
+            if !worker_discarded.load(Ordering::Acquire) {
+                let analysis = dwell.finish();
+                // Only fails once the scan has stopped listening
+                let _ = events.blocking_send(Event::Analysed(analysis));
+            }
+            drop(worker);
+        });
+        Dwell {
+            blocks: sender,
+            realtime,
+            sent: 0,
+            dropped: 0,
+            discarded,
+        }
+    }
+
+    /// Closes a dwell so its worker gets on with the analysis.
+    fn finish(&self, dwell: Dwell, freq: u64) {
+        if dwell.dropped > 0 {
+            self.send(Event::Dropped {
+                freq,
+                dropped: dwell.dropped,
+                blocks: dwell.sent,
+            });
+        }
+    }
+
+    /// Closes a dwell whose worker is to stop without reporting.
+    fn discard(&self, dwell: Dwell) {
+        dwell.discarded.store(true, Ordering::Release);
+    }
+
+    /// Closes the partial dwell of a failed capture, if it got that far.
+    fn finish_partial(&self, failure: &mut CaptureFailure, freq: u64, keep: bool) {
+        match failure.partial.take() {
+            Some(dwell) if keep => self.finish(dwell, freq),
+            Some(dwell) => self.discard(dwell),
+            None => {}
+        }
+    }
+
+    /// Waits until every dwell handed out so far has been analysed.
+    fn drain(&self) {
+        let all = self.runtime.block_on(self.permits.acquire_many(self.count));
+        drop(all);
+    }
+
+    fn send(&self, event: Event) {
+        let _ = self.events.blocking_send(event);
+    }
+
+    /// Whether the scan has stopped taking results.
+    fn abandoned(&self) -> bool {
+        self.events.is_closed()
+    }
+}
+
+fn scan_freq(
+    source: &mut dyn SdrSource,
+    band: &Band,
+    step: &Step,
+    gain: &mut GainControl,
+    workers: &Workers,
+    finished: &dyn Fn() -> bool,
+) -> Result<(), CaptureFailure> {
+    let worker = workers.reserve();
+    source.set_sample_rate(band.sample_rate)?;
+    source.set_gains(gain.gains())?;
+
+    let retune_start = Instant::now();
+    source.tune(step.center_freq)?;
+    let retune_latency = retune_start.elapsed();
+
+    let gains = gain.settle(source)?;
+
+    let wanted = iq_bytes(band.sample_rate, band.dwell());
+    let mut dwell = workers.start(
+        worker,
+        band,
+        step,
+        retune_latency,
+        source.supports_gain().then_some(gains),
+        source.realtime(),
+    );
+
+    // A long dwell is cut short rather than holding up the end of the scan
+    let mut received = 0;
+    while received < wanted && !finished() {
+        match source.read_block() {
+            Ok(samples) if samples.is_empty() => break,
+            Ok(samples) => {
+                received += samples.len();
+                dwell.send(samples);
+            }
+            Err(error) => {
+                return Err(CaptureFailure {
+                    error,
+                    partial: Some(dwell),
+                })
+            }
+        }
+    }
+    workers.finish(dwell, step.center_freq);
+    Ok(())
+}
+
+/// `scan_freq`, retried while the failure looks transient. Gives up
+/// quietly once the attempts run out on a setting the device keeps
+/// refusing, so the sweep can carry on without this tuning. A device that
+/// stays lost, or anything that will not clear up, is returned as the error
+/// along with any partial dwell, for the sweep to keep or throw away.
+fn capture_with_retry(
+    source: &mut dyn SdrSource,
+    band: &Band,
+    step: &Step,
+    gain: &mut GainControl,
+    workers: &Workers,
+    finished: &dyn Fn() -> bool,
+) -> Result<(), CaptureFailure> {
+    let frequency = step.center_freq;
+    let mut attempt = 1;
+    loop {
+        let mut failure = match scan_freq(source, band, step, gain, workers, finished) {
+            Ok(()) => return Ok(()),
+            Err(failure) => failure,
+        };
+        let error = &failure.error;
+        if error.is_transient() && attempt < CAPTURE_ATTEMPTS {
+            println!(
+                "Capture at {} MHz failed (attempt {} of {}): {}",
+                frequency as f64 / 1_000_000.0,
+                attempt,
+                CAPTURE_ATTEMPTS,
+                error
+            );
+            workers.finish_partial(&mut failure, frequency, false);
+        } else if error.is_transient() && !error.is_device_lost() {
+            println!("Skipping {} MHz: {}", frequency as f64 / 1_000_000.0, error);
+            workers.finish_partial(&mut failure, frequency, true);
+            return Ok(());
+        } else {
+            return Err(failure);
+        }
+        attempt += 1;
+    }
+}
+
+/// Keeps trying to reopen a lost device, backing off between attempts.
+/// Returns false if `finished` says the scan ran out, or it was stopped,
+/// before the device came back.
+async fn reconnect(
+    source: &mut dyn SdrSource,
+    shutdown: &Shutdown,
+    finished: impl Fn() -> bool,
+) -> bool {
+    let mut backoff = RECONNECT_BACKOFF;
+    while !finished() {
+        println!("Reconnecting in {} s...", backoff.as_secs());
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.wait() => return false,
+        }
+        match source.reconnect() {
+            Ok(()) => {
+                println!("Device reconnected");
+                return true;
+            }
+            Err(error) => println!("Reconnect failed: {}", error),
+        }
+        backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+    }
+    false
+}
+
+/// The capture thread: sweeps the bands once for an instant scan, or over
+/// and over until `length` runs out or the scan is stopped. Returns the
+/// failure that ended it early, if one did.
+fn sweep(
+    source: &mut dyn SdrSource,
+    bands: &[Band],
+    gain: GainConfig,
+    mode: ScanMode,
+    length: Option<Duration>,
+    shutdown: &Shutdown,
+    workers: &Workers,
+) -> Option<ScanError> {
+    let origin = workers.origin;
+    let finished = || {
+        shutdown.requested()
+            || workers.abandoned()
+            || length.is_some_and(|length| origin.elapsed() >= length)
+    };
+
+    let mut plan: Vec<(&Band, Vec<Step>, GainControl)> = bands
+        .iter()
+        .map(|band| {
+            let gain_control = GainControl::new(band.gain.unwrap_or(gain));
+            (band, band.steps(), gain_control)
+        })
+        .collect();
+
+    'sweep: loop {
+        for (band, steps, gain_control) in &mut plan {
+            if mode == ScanMode::Instant {
+                println!(
+                    "Scanning band {} ({} - {} MHz)",
+                    band.name,
+                    band.start_freq as f64 / 1_000_000.0,
+                    band.end_freq as f64 / 1_000_000.0
+                );
+            }
+            for step in steps.iter() {
+                if finished() {
+                    break 'sweep;
+                }
+
+                // Picks up at this same tuning after a reconnection
+                loop {
+                    let attempted = origin.elapsed();
+                    match capture_with_retry(source, band, step, gain_control, workers, &finished) {
+                        Ok(()) => break,
+                        // An instant scan is over too soon to wait for it
+                        Err(mut failure)
+                            if failure.error.is_device_lost() && mode != ScanMode::Instant =>
+                        {
+                            println!("Lost the device: {}", failure.error);
+                            let back = workers
+                                .runtime
+                                .block_on(reconnect(source, shutdown, &finished));
+                            // Once the device is back this tuning is captured again
+                            workers.finish_partial(&mut failure, step.center_freq, !back);
+                            workers.send(Event::Gap(CoverageGap::new(
+                                &workers.clock,
+                                attempted,
+                                origin.elapsed(),
+                                failure.error.to_string(),
+                            )));
+                            if !back {
+                                break 'sweep;
+                            }
+                        }
+                        Err(mut failure) => {
+                            workers.finish_partial(&mut failure, step.center_freq, true);
+                            return Some(failure.error);
+                        }
+                    }
+                }
+            }
+        }
+
+        match mode {
+            ScanMode::Instant => break,
+            ScanMode::Scheduled => {}
+            ScanMode::Monitor => {
+                workers.drain();
+                workers.send(Event::SweepDone(source.device_info()));
+            }
+        }
+    }
+    None
+}
+
+/// Runs a scan through the pipeline and writes its report to `output`,
+/// partial if the scan was stopped or failed part way. Monitoring also
+/// rewrites it after every complete sweep, so it is never more than a
+/// sweep behind.
+pub async fn run(
+    mut source: Box<dyn SdrSource>,
+    config: &Config,
+    bands: &[Band],
+    mode: ScanMode,
+    length: Option<Duration>,
+    output: &str,
+    shutdown: &Shutdown,
+) -> Result<ScanReport, ScanError> {
+    let clock = ScanClock::start();
+    let count = worker_count();
+    let (events, mut received) = mpsc::channel(EVENT_QUEUE);
+    let workers = Workers {
+        permits: Arc::new(Semaphore::new(count as usize)),
+        count,
+        runtime: Handle::current(),
+        detection: Arc::new(config.detection.clone()),
+        clock,
+        origin: Instant::now(),
+        events,
+    };
+
+    let capture = {
+        let (bands, gain, shutdown) = (bands.to_vec(), config.gain, shutdown.clone());
+        tokio::task::spawn_blocking(move || {
+            let failure = sweep(
+                source.as_mut(),
+                &bands,
+                gain,
+                mode,
+                length,
+                &shutdown,
+                &workers,
+            );
+            (source.device_info(), failure)
+        })
+    };
+
+    let mut findings = Findings::default();
+    // A failure that ends the scan early; what was found until then is
+    // still reported
+    let mut failure = None;
+    while let Some(event) = received.recv().await {
+        match event {
+            Event::Analysed(analysis) => {
+                if mode == ScanMode::Instant {
+                    for line in &analysis.log {
+                        println!("{}", line);
+                    }
+                }
+                findings.add(analysis);
+            }
+            Event::Dropped {
+                freq,
+                dropped,
+                blocks,
+            } => {
+                println!(
+                    "Analysis fell behind at {} MHz, dropped {} of {} blocks",
+                    freq as f64 / 1_000_000.0,
+                    dropped,
+                    blocks
+                );
+                findings.add_dropped(dropped);
+            }
+            Event::Gap(gap) => findings.add_gap(gap),
+            Event::SweepDone(device) => {
+                let checkpoint = findings.report(mode, device, &clock, config, bands, false);
+                if let Err(error) = checkpoint.write(output) {
+                    failure = Some(error);
+                    break;
+                }
+            }
+        }
+    }
+    // Stops the capture thread if it is still going
+    drop(received);
+
+    let (device, capture_failure) = match capture.await {
+        Ok(ended) => ended,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    };
+    let failure = failure.or(capture_failure);
+
+    let completed = failure.is_none() && !shutdown.requested();
+    let report = findings.report(mode, device, &clock, config, bands, completed);
+    report.write(output)?;
+
+    if !completed {
+        println!("Scan stopped early, partial results written to {}", output);
+    }
+    match failure {
+        Some(error) => Err(error),
+        None => Ok(report),
+    }
+}
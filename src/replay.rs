@@ -0,0 +1,217 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::PathBuf;
+
+use crate::dsp::lowpass_taps;
+use crate::error::ScanError;
+use crate::source::{DeviceInfo, Gains, SdrSource};
+
+// Same transfer size the HackRF hands back from a single `rx()` (128 KiB)
+const BLOCK_SIZE: usize = 131_072;
+const TAPS_PER_PHASE: usize = 16;
+
+/// Replays a `hackrf_transfer` style capture (interleaved signed 8-bit I/Q).
+///
+/// Every tune rewinds to the start of the file. Tuning to the capture's own
+/// center frequency and sample rate returns the recorded bytes untouched;
+/// anything else is mixed down, low-pass filtered and decimated so the
+/// requested sub-band comes out at the requested rate. Tunings that fall
+/// outside the captured bandwidth yield no samples.
+pub struct ReplaySource {
+    path: PathBuf,
+    center_freq: u64,
+    capture_rate: u32,
+    frequency: u64,
+    sample_rate: u32,
+    reader: Option<BufReader<File>>,
+    sub_band: Option<SubBand>,
+    exhausted: bool,
+}
+
+impl ReplaySource {
+    pub fn new(path: impl Into<PathBuf>, center_freq: u64, capture_rate: u32) -> Self {
+        ReplaySource {
+            path: path.into(),
+            center_freq,
+            capture_rate,
+            frequency: center_freq,
+            sample_rate: capture_rate,
+            reader: None,
+            sub_band: None,
+            exhausted: false,
+        }
+    }
+
+    fn rewind(&mut self) {
+        self.reader = None;
+        self.sub_band = None;
+        self.exhausted = false;
+    }
+
+    fn start(&mut self) -> Result<(), ScanError> {
+        if self.sample_rate == 0 || !self.capture_rate.is_multiple_of(self.sample_rate) {
+            return Err(ScanError::Config(format!(
+                "Replay sample rate {} Hz must evenly divide the capture rate {} Hz",
+                self.sample_rate, self.capture_rate
+            )));
+        }
+
+        let offset = self.frequency as i64 - self.center_freq as i64;
+        let half_capture = self.capture_rate as i64 / 2;
+        let half_wanted = self.sample_rate as i64 / 2;
+        if offset.abs() + half_wanted > half_capture {
+            self.exhausted = true;
+            return Ok(());
+        }
+
+        let file = File::open(&self.path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to open capture {}: {}", self.path.display(), e),
+            )
+        })?;
+        self.reader = Some(BufReader::new(file));
+
+        if offset != 0 || self.sample_rate != self.capture_rate {
+            let decimation = (self.capture_rate / self.sample_rate) as usize;
+            self.sub_band = Some(SubBand::new(offset, self.capture_rate, decimation));
+        }
+        Ok(())
+    }
+}
+
+impl SdrSource for ReplaySource {
+    fn tune(&mut self, frequency: u64) -> Result<(), ScanError> {
+        self.frequency = frequency;
+        self.rewind();
+        Ok(())
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), ScanError> {
+        self.sample_rate = sample_rate;
+        self.rewind();
+        Ok(())
+    }
+
+    fn set_gains(&mut self, _gains: Gains) -> Result<(), ScanError> {
+        // Gains were fixed when the capture was recorded
+        Ok(())
+    }
+
+    fn read_block(&mut self) -> Result<Vec<u8>, ScanError> {
+        if self.exhausted {
+            return Ok(Vec::new());
+        }
+        if self.reader.is_none() {
+            self.start()?;
+            if self.exhausted {
+                return Ok(Vec::new());
+            }
+        }
+
+        let decimation = self.sub_band.as_ref().map_or(1, |s| s.decimation);
+        let reader = self.reader.as_mut().expect("capture was just opened");
+        let raw = read_up_to(reader, BLOCK_SIZE * decimation)?;
+        if raw.len() < 2 {
+            self.exhausted = true;
+            return Ok(Vec::new());
+        }
+
+        match self.sub_band.as_mut() {
+            Some(sub_band) => Ok(sub_band.process(&raw)),
+            None => Ok(raw),
+        }
+    }
+
+    fn supports_gain(&self) -> bool {
+        false
+    }
+
+    fn realtime(&self) -> bool {
+        false
+    }
+
+    fn device_info(&self) -> DeviceInfo {
+        DeviceInfo {
+            driver: "replay".to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+fn read_up_to(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; len];
+    let mut filled = 0;
+    while filled < len {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    // Never hand out half an I/Q pair
+    buf.truncate(filled & !1);
+    Ok(buf)
+}
+
+/// Mixer, low-pass FIR and decimator that pulls one sub-band out of a
+/// wideband capture. The oscillator phase is tracked as an exact integer
+/// so output is identical on every run.
+struct SubBand {
+    offset: i64,
+    capture_rate: i64,
+    phase: i64,
+    decimation: usize,
+    taps: Vec<f64>,
+    history: Vec<(f64, f64)>,
+}
+
+impl SubBand {
+    fn new(offset: i64, capture_rate: u32, decimation: usize) -> Self {
+        let len = TAPS_PER_PHASE * decimation + 1;
+        let taps = lowpass_taps(len, 0.5 / decimation as f64);
+
+        SubBand {
+            offset,
+            capture_rate: capture_rate as i64,
+            phase: 0,
+            decimation,
+            history: vec![(0.0, 0.0); len - 1],
+            taps,
+        }
+    }
+
+    fn process(&mut self, raw: &[u8]) -> Vec<u8> {
+        for pair in raw.chunks_exact(2) {
+            let i = pair[0] as i8 as f64;
+            let q = pair[1] as i8 as f64;
+            let angle = -2.0 * PI * self.phase as f64 / self.capture_rate as f64;
+            let (sin, cos) = angle.sin_cos();
+            self.history.push((i * cos - q * sin, i * sin + q * cos));
+            self.phase = (self.phase + self.offset).rem_euclid(self.capture_rate);
+        }
+
+        let len = self.taps.len();
+        let mut out = Vec::with_capacity(2 * self.history.len() / self.decimation + 2);
+        let mut start = 0;
+        while start + len <= self.history.len() {
+            let (mut acc_i, mut acc_q) = (0.0, 0.0);
+            for (tap, &(i, q)) in self.taps.iter().zip(&self.history[start..start + len]) {
+                acc_i += tap * i;
+                acc_q += tap * q;
+            }
+            out.push(quantise(acc_i));
+            out.push(quantise(acc_q));
+            start += self.decimation;
+        }
+
+        // Keep the tail the next block's first output still needs
+        self.history.drain(..start);
+        out
+    }
+}
+
+fn quantise(value: f64) -> u8 {
+    value.round().clamp(-128.0, 127.0) as i8 as u8
+}
@@ -0,0 +1,341 @@
+use chrono::{DateTime, Utc};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+
+use crate::activity::{record, ActivityInterval, CoverageGap, ScanClock};
+use crate::band::Band;
+use crate::bsch::SyncInfo;
+use crate::burst::Modulation;
+use crate::carrier::Carrier;
+use crate::config::Config;
+use crate::error::ScanError;
+use crate::gain::GainConfig;
+use crate::slots::{carrier_occupancy, SlotOccupancy};
+use crate::source::{DeviceInfo, Gains};
+use crate::sysinfo::SysInfo;
+
+/// Bumped whenever the shape of the report changes in a way readers notice.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Everything one scan found, as written to the output file.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ScanReport {
+    #[schemars(schema_with = "current_version")]
+    pub schema_version: u32,
+    pub metadata: ScanMetadata,
+    /// Every channel something was heard on, in frequency order. Empty when
+    /// nothing was.
+    pub channels: Vec<ChannelResult>,
+    /// Times nothing was received, so silence then means nothing
+    #[serde(default)]
+    pub coverage_gaps: Vec<CoverageGap>,
+}
+
+// Pins the version in the published schema, so a reader checking against
+// it rejects reports of another shape outright
+fn current_version(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::Integer.into()),
+        const_value: Some(SCHEMA_VERSION.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanMode {
+    Instant,
+    Scheduled,
+    /// Scheduled sweeps with no countdown, reported after every pass
+    Monitor,
+}
+
+// Reports from before the marker was added were only written on completion
+fn completed_by_default() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ScanMetadata {
+    pub tool_version: String,
+    pub mode: ScanMode,
+    pub device: DeviceInfo,
+    pub started_utc: DateTime<Utc>,
+    /// When the scan stopped, whether or not it ran its course
+    pub finished_utc: DateTime<Utc>,
+    /// False when the scan was interrupted or failed part way, so the
+    /// results cover less than was planned
+    #[serde(default = "completed_by_default")]
+    pub completed: bool,
+    /// Blocks of samples thrown away because analysis could not keep up
+    /// with the radio. Channels may have been missed while they were.
+    #[serde(default)]
+    pub dropped_blocks: u64,
+    /// The band plan with presets resolved
+    pub bands: Vec<Band>,
+    /// Gains used wherever a band does not override them
+    pub gain: GainConfig,
+    pub config: Config,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "link", rename_all = "lowercase")]
+pub enum ChannelResult {
+    Downlink(DownlinkResult),
+    Uplink(UplinkResult),
+}
+
+/// A base station carrier and what could be decoded from it. Frequencies
+/// are in MHz.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct DownlinkResult {
+    pub freq: f64,
+    pub band: String,
+    pub carrier: Carrier,
+    /// Paired uplink, from SYSINFO if it was decoded
+    pub uplink_freq: Option<f64>,
+    pub strength_dbfs: f64,
+    pub peak_dbfs: f64,
+    pub noise_floor_dbfs: f64,
+    pub snr_db: f64,
+    pub sample_count: usize,
+    pub retune_ms: f64,
+    pub gains: Option<Gains>,
+    /// Where the demodulator measured the carrier, relative to `freq`
+    pub carrier_offset_hz: Option<f64>,
+    pub phase_error_deg: Option<f64>,
+    pub modulation: Option<Modulation>,
+    pub confidence: Option<f64>,
+    pub sync: Option<SyncInfo>,
+    pub sysinfo: Option<SysInfo>,
+    /// Whether this is the cell's main control carrier, per its SYSINFO
+    pub control_channel: Option<bool>,
+    pub slot_occupancy_percent: Option<f64>,
+    pub slots: Option<Vec<SlotOccupancy>>,
+    pub activity: Vec<ActivityInterval>,
+}
+
+/// An uplink channel mobiles were heard transmitting on. Frequencies are
+/// in MHz.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct UplinkResult {
+    pub freq: f64,
+    pub band: String,
+    pub downlink_freq: Option<f64>,
+    /// Numbered as its paired downlink, which the carrier number names both
+    /// halves of
+    pub carrier: Option<Carrier>,
+    pub noise_floor_dbfs: f64,
+    pub sample_count: usize,
+    pub retune_ms: f64,
+    pub gains: Option<Gains>,
+    /// From the training sequences of the bursts that could be demodulated
+    pub modulation: Option<Modulation>,
+    pub confidence: Option<f64>,
+    /// One interval per burst, or run of back-to-back bursts
+    pub activity: Vec<ActivityInterval>,
+}
+
+// Whether a classification beats the one kept so far; any verdict beats none
+fn more_confident(candidate: Option<f64>, kept: Option<f64>) -> bool {
+    candidate.is_some_and(|candidate| kept.is_none_or(|kept| candidate > kept))
+}
+
+impl DownlinkResult {
+    /// Folds in another dwell on the same carrier: its activity and slots
+    /// are added, the power and the classification kept from whichever
+    /// dwell measured them best, and what only one dwell decoded filled in.
+    pub fn merge(&mut self, mut other: DownlinkResult) {
+        for interval in std::mem::take(&mut other.activity) {
+            record(&mut self.activity, interval);
+        }
+        if let Some(slots) = other.slots.take() {
+            self.merge_slots(slots);
+        }
+
+        self.peak_dbfs = self.peak_dbfs.max(other.peak_dbfs);
+        if other.strength_dbfs > self.strength_dbfs {
+            self.strength_dbfs = other.strength_dbfs;
+            self.noise_floor_dbfs = other.noise_floor_dbfs;
+            self.snr_db = other.snr_db;
+            self.sample_count = other.sample_count;
+            self.retune_ms = other.retune_ms;
+            self.gains = other.gains;
+        }
+
+        if more_confident(other.confidence, self.confidence) {
+            self.modulation = other.modulation;
+            self.confidence = other.confidence;
+            self.carrier_offset_hz = other.carrier_offset_hz;
+            self.phase_error_deg = other.phase_error_deg;
+        }
+
+        self.sync = self.sync.or(other.sync);
+        // The uplink and control channel follow SYSINFO when a dwell has it
+        if self.sysinfo.is_none() && other.sysinfo.is_some() {
+            self.sysinfo = other.sysinfo;
+            self.uplink_freq = other.uplink_freq;
+            self.control_channel = other.control_channel;
+        } else {
+            self.uplink_freq = self.uplink_freq.or(other.uplink_freq);
+            self.control_channel = self.control_channel.or(other.control_channel);
+        }
+    }
+
+    /// Adds the slot tallies of a later dwell on the same carrier.
+    pub fn merge_slots(&mut self, slots: Vec<SlotOccupancy>) {
+        match &mut self.slots {
+            Some(totals) => {
+                for (total, slot) in totals.iter_mut().zip(&slots) {
+                    total.merge(slot);
+                }
+            }
+            None => self.slots = Some(slots),
+        }
+        self.slot_occupancy_percent = self.slots.as_deref().map(carrier_occupancy);
+    }
+}
+
+impl UplinkResult {
+    /// Folds in another dwell on the same channel, keeping the more
+    /// confident classification.
+    pub fn merge(&mut self, mut other: UplinkResult) {
+        for interval in std::mem::take(&mut other.activity) {
+            record(&mut self.activity, interval);
+        }
+        if more_confident(other.confidence, self.confidence) {
+            self.modulation = other.modulation;
+            self.confidence = other.confidence;
+        }
+    }
+}
+
+impl ChannelResult {
+    pub fn freq(&self) -> f64 {
+        match self {
+            ChannelResult::Downlink(result) => result.freq,
+            ChannelResult::Uplink(result) => result.freq,
+        }
+    }
+}
+
+impl ScanMetadata {
+    pub fn new(
+        mode: ScanMode,
+        device: DeviceInfo,
+        clock: &ScanClock,
+        config: &Config,
+        bands: &[Band],
+        completed: bool,
+    ) -> Self {
+        ScanMetadata {
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            mode,
+            device,
+            started_utc: clock.started_utc(),
+            finished_utc: Utc::now(),
+            completed,
+            dropped_blocks: 0,
+            bands: bands.to_vec(),
+            gain: config.gain,
+            config: config.clone(),
+        }
+    }
+}
+
+impl ScanReport {
+    pub fn new(metadata: ScanMetadata, mut channels: Vec<ChannelResult>) -> Self {
+        channels.sort_by(|a, b| a.freq().total_cmp(&b.freq()));
+        ScanReport {
+            schema_version: SCHEMA_VERSION,
+            metadata,
+            channels,
+            coverage_gaps: Vec::new(),
+        }
+    }
+
+    /// Prints the report and writes it to `path`.
+    pub fn write(&self, path: &str) -> Result<(), ScanError> {
+        let json = serde_json::to_string_pretty(self)?;
+        println!("{}", json);
+
+        let mut file = File::create(path)?;
+        file.write_all(json.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dwell(strength_dbfs: f64, modulation: Modulation, confidence: f64) -> DownlinkResult {
+        DownlinkResult {
+            freq: 390.5125,
+            band: "test".to_string(),
+            carrier: Carrier::from_downlink(390_512_500),
+            uplink_freq: Some(380.5125),
+            strength_dbfs,
+            peak_dbfs: strength_dbfs + 3.0,
+            noise_floor_dbfs: -50.0,
+            snr_db: strength_dbfs + 50.0,
+            sample_count: 1000,
+            retune_ms: 1.0,
+            gains: None,
+            carrier_offset_hz: Some(0.0),
+            phase_error_deg: Some(10.0),
+            modulation: Some(modulation),
+            confidence: Some(confidence),
+            sync: None,
+            sysinfo: None,
+            control_channel: None,
+            slot_occupancy_percent: None,
+            slots: None,
+            activity: Vec::new(),
+        }
+    }
+
+    fn sync() -> SyncInfo {
+        SyncInfo {
+            system_code: 0,
+            colour_code: 1,
+            timeslot: 1,
+            frame: 1,
+            multiframe: 1,
+            sharing_mode: 0,
+            mcc: 262,
+            mnc: 1,
+            cell_service_level: 0,
+            late_entry: false,
+        }
+    }
+
+    #[test]
+    fn a_later_dwell_fills_in_and_improves_on_the_first() {
+        let mut kept = dwell(-40.0, Modulation::Unknown, 0.6);
+        let mut later = dwell(-30.0, Modulation::Tetra, 0.9);
+        later.sync = Some(sync());
+        kept.merge(later);
+        assert_eq!(kept.sync, Some(sync()));
+        assert_eq!(kept.modulation, Some(Modulation::Tetra));
+        assert_eq!(kept.confidence, Some(0.9));
+        assert_eq!(kept.strength_dbfs, -30.0);
+        assert_eq!(kept.snr_db, 20.0);
+    }
+
+    #[test]
+    fn a_weaker_less_certain_dwell_changes_nothing_it_measured() {
+        let mut kept = dwell(-30.0, Modulation::Tetra, 0.9);
+        kept.sync = Some(sync());
+        kept.merge(dwell(-40.0, Modulation::Unknown, 0.6));
+        assert_eq!(kept.sync, Some(sync()));
+        assert_eq!(kept.modulation, Some(Modulation::Tetra));
+        assert_eq!(kept.strength_dbfs, -30.0);
+        assert_eq!(kept.peak_dbfs, -27.0);
+    }
+}
@@ -0,0 +1,119 @@
+//! JSON Schema for the scan report, generated from the result types. A test
+//! fails when the published copy in `schema/` no longer matches what the
+//! scanner writes; regenerate it with `tetra_module schema` after changing
+//! the report, and bump `SCHEMA_VERSION` if old outputs stop validating.
+
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::JSONSchema;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+use serde_json::Value;
+use std::fs::File;
+
+use crate::error::ScanError;
+use crate::report::{DownlinkResult, ScanReport, UplinkResult, SCHEMA_VERSION};
+
+pub fn report_schema() -> RootSchema {
+    schema_for!(ScanReport)
+}
+
+/// Checks an output file against the schema this build writes, returning
+/// one line per incompatibility. Empty when the file is compatible.
+pub fn validate_report(path: &str) -> Result<Vec<String>, ScanError> {
+    let file = File::open(path)?;
+    let report: Value = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| ScanError::Report(format!("{} is not JSON: {}", path, e)))?;
+
+    let mut problems = Vec::new();
+    let version = report.get("schema_version").and_then(Value::as_u64);
+    let version_mismatch = version.is_some_and(|v| v != SCHEMA_VERSION as u64);
+    match version {
+        None => problems.push(
+            "No schema_version: written before outputs were versioned, or not a scan report"
+                .to_string(),
+        ),
+        Some(v) if version_mismatch => problems.push(format!(
+            "Written with schema version {}, this build reads version {}",
+            v, SCHEMA_VERSION
+        )),
+        Some(_) => {}
+    }
+
+    for (at, problem) in check(report_schema(), &report, "")? {
+        // Already reported above, more readably
+        if at == "/schema_version" && version_mismatch {
+            continue;
+        }
+        problems.push(problem);
+    }
+    Ok(problems)
+}
+
+/// Validates `instance` against `schema`, as (path, message) pairs. A
+/// channel that matches neither link is checked again against the one its
+/// `link` tag names, so the problem is reported field by field rather than
+/// as a failed `oneOf`.
+fn check(
+    schema: RootSchema,
+    instance: &Value,
+    prefix: &str,
+) -> Result<Vec<(String, String)>, ScanError> {
+    let schema = serde_json::to_value(schema)?;
+    let compiled = JSONSchema::compile(&schema).map_err(|e| ScanError::Report(e.to_string()))?;
+    let errors: Vec<_> = match compiled.validate(instance) {
+        Ok(()) => return Ok(Vec::new()),
+        Err(errors) => errors
+            .map(|error| {
+                let one_of = matches!(error.kind, ValidationErrorKind::OneOfNotValid);
+                let at = format!("{}{}", prefix, error.instance_path);
+                (at, one_of, error.to_string())
+            })
+            .collect(),
+    };
+
+    let mut problems = Vec::new();
+    for (at, one_of, message) in errors {
+        let shown = if at.is_empty() { "/" } else { at.as_str() };
+        if !one_of {
+            problems.push((at.clone(), format!("{}: {}", shown, message)));
+            continue;
+        }
+
+        let value = instance
+            .pointer(&at[prefix.len()..])
+            .unwrap_or(&Value::Null);
+        match value.get("link").and_then(Value::as_str) {
+            Some("downlink") => problems.extend(check(schema_for!(DownlinkResult), value, &at)?),
+            Some("uplink") => problems.extend(check(schema_for!(UplinkResult), value, &at)?),
+            Some(link) => problems.push((
+                at.clone(),
+                format!(
+                    "{}: unknown link '{}', expected downlink or uplink",
+                    shown, link
+                ),
+            )),
+            None => problems.push((
+                at.clone(),
+                format!("{}: does not match any of the shapes allowed here", shown),
+            )),
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn published_schema_matches_the_report_types() {
+        let published: Value =
+            serde_json::from_str(include_str!("../schema/scan_report.v1.schema.json"))
+                .expect("published schema is JSON");
+        let generated = serde_json::to_value(report_schema()).expect("schema serialises");
+        assert!(
+            published == generated,
+            "schema/scan_report.v1.schema.json is stale, regenerate it with `tetra_module schema`"
+        );
+    }
+}
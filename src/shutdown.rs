@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Set once Ctrl-C or SIGTERM arrives. Sweeps check it between tunings so
+/// they can stop cleanly and still write what they found.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Shutdown {
+    /// Starts listening for the signals in the background.
+    pub fn listen() -> Self {
+        let shutdown = Shutdown::default();
+        let (requested, notify) = (shutdown.requested.clone(), shutdown.notify.clone());
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            println!("Stopping, results so far will be written...");
+            requested.store(true, Ordering::SeqCst);
+            notify.notify_waiters();
+
+            // A second interrupt means it should not wait for that
+            wait_for_signal().await;
+            println!("Stopping immediately");
+            std::process::exit(130);
+        });
+        shutdown
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once a stop has been asked for, for cutting waits short.
+    pub async fn wait(&self) {
+        // Registered before the check so a signal in between still wakes it
+        let notified = self.notify.notified();
+        if self.requested() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(_) => ctrl_c().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    ctrl_c().await
+}
+
+// Without a handler there is nothing to wait for, rather than a stop
+async fn ctrl_c() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
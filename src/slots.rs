@@ -0,0 +1,157 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::bsch::decode_bsch;
+use crate::burst::{
+    BurstKind, Classification, BURST_BITS, FIRST_HALF_OFFSET, HALF_SLOT_BITS, SECOND_HALF_OFFSET,
+};
+use crate::coding::{
+    decode_block, field, scrambling_code, FULL_SLOT_INTERLEAVE_A, HALF_SLOT_INTERLEAVE_A,
+};
+
+const TIMESLOTS: usize = 4;
+const FRAMES_PER_MULTIFRAME: i64 = 18;
+// Frame 18 only ever carries control, so it says nothing about load
+const CONTROL_FRAME: u8 = 18;
+
+const MAC_RESOURCE: u32 = 0b00;
+const NULL_PDU_ADDRESS: u32 = 0b000;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlotUsage {
+    /// Speech or circuit data: a normal burst with no decodable signalling
+    Traffic,
+    Control,
+    /// Signalling blocks that only carry null PDUs
+    Idle,
+}
+
+/// How one timeslot of a carrier was used across the bursts heard in it.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default)]
+pub struct SlotOccupancy {
+    /// 1 to 4
+    pub timeslot: u8,
+    pub traffic: usize,
+    pub control: usize,
+    pub idle: usize,
+    /// Share of bursts carrying traffic
+    pub occupancy_percent: f64,
+}
+
+impl SlotOccupancy {
+    pub fn new(timeslot: u8) -> Self {
+        SlotOccupancy {
+            timeslot,
+            ..Default::default()
+        }
+    }
+
+    fn observed(&self) -> usize {
+        self.traffic + self.control + self.idle
+    }
+
+    fn count(&mut self, usage: SlotUsage) {
+        match usage {
+            SlotUsage::Traffic => self.traffic += 1,
+            SlotUsage::Control => self.control += 1,
+            SlotUsage::Idle => self.idle += 1,
+        }
+        self.update_percent();
+    }
+
+    fn update_percent(&mut self) {
+        self.occupancy_percent = match self.observed() {
+            0 => 0.0,
+            observed => self.traffic as f64 * 100.0 / observed as f64,
+        };
+    }
+
+    /// Adds the bursts of a later dwell on the same timeslot.
+    pub fn merge(&mut self, other: &SlotOccupancy) {
+        self.traffic += other.traffic;
+        self.control += other.control;
+        self.idle += other.idle;
+        self.update_percent();
+    }
+}
+
+/// Share of traffic bursts over all four timeslots.
+pub fn carrier_occupancy(slots: &[SlotOccupancy]) -> f64 {
+    let observed: usize = slots.iter().map(SlotOccupancy::observed).sum();
+    let traffic: usize = slots.iter().map(|slot| slot.traffic).sum();
+    if observed == 0 {
+        0.0
+    } else {
+        traffic as f64 * 100.0 / observed as f64
+    }
+}
+
+fn pdu_usage(pdu: &[u8]) -> SlotUsage {
+    if field(pdu, 0, 2) == MAC_RESOURCE && field(pdu, 13, 3) == NULL_PDU_ADDRESS {
+        SlotUsage::Idle
+    } else {
+        SlotUsage::Control
+    }
+}
+
+/// Whatever decodes in a burst decides its use: any real signalling makes
+/// it control, null PDUs alone make it idle and nothing at all makes it
+/// traffic.
+fn burst_usage(bits: &[u8], start: usize, kind: BurstKind, seed: u32) -> Option<SlotUsage> {
+    let first = bits.get(start + FIRST_HALF_OFFSET..start + FIRST_HALF_OFFSET + HALF_SLOT_BITS)?;
+    let second =
+        bits.get(start + SECOND_HALF_OFFSET..start + SECOND_HALF_OFFSET + HALF_SLOT_BITS)?;
+
+    let pdus: Vec<Vec<u8>> = match kind {
+        BurstKind::Sync => return Some(SlotUsage::Control),
+        BurstKind::NormalSplit => [first, second]
+            .iter()
+            .filter_map(|block| decode_block(block, seed, HALF_SLOT_INTERLEAVE_A))
+            .collect(),
+        BurstKind::NormalFull => {
+            decode_block(&[first, second].concat(), seed, FULL_SLOT_INTERLEAVE_A)
+                .into_iter()
+                .collect()
+        }
+    };
+
+    if pdus.is_empty() {
+        Some(SlotUsage::Traffic)
+    } else if pdus.iter().all(|pdu| pdu_usage(pdu) == SlotUsage::Idle) {
+        Some(SlotUsage::Idle)
+    } else {
+        Some(SlotUsage::Control)
+    }
+}
+
+/// Numbers every burst on the carrier from the first clean BSCH and tallies
+/// how each timeslot is used outside the control frame. Needs a decodable
+/// synchronisation burst for both the frame timing and the scrambling code.
+pub fn slot_occupancy(bits: &[u8], classification: &Classification) -> Option<Vec<SlotOccupancy>> {
+    let (reference, sync) = classification
+        .bursts
+        .iter()
+        .filter(|burst| burst.kind == BurstKind::Sync)
+        .find_map(|burst| decode_bsch(bits, burst.start).map(|sync| (burst.start, sync)))?;
+    let seed = scrambling_code(sync.mcc, sync.mnc, sync.colour_code);
+
+    let mut slots: Vec<SlotOccupancy> = (1..=TIMESLOTS as u8).map(SlotOccupancy::new).collect();
+
+    for burst in &classification.bursts {
+        // Slots since the reference burst's frame began
+        let slot = (burst.start as i64 - reference as i64) / BURST_BITS as i64
+            + (sync.timeslot - 1) as i64;
+        let timeslot = slot.rem_euclid(TIMESLOTS as i64) as usize;
+        let frame = (sync.frame as i64 - 1 + slot.div_euclid(TIMESLOTS as i64))
+            .rem_euclid(FRAMES_PER_MULTIFRAME) as u8
+            + 1;
+        if frame == CONTROL_FRAME {
+            continue;
+        }
+        if let Some(usage) = burst_usage(bits, burst.start, burst.kind, seed) {
+            slots[timeslot].count(usage);
+        }
+    }
+    Some(slots)
+}
@@ -0,0 +1,230 @@
+use hackrfone::{HackRfOne, RxMode, UnknownMode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ScanError;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Gains {
+    pub amp: bool,
+    pub lna: u16,
+    pub vga: u16,
+}
+
+/// Tuning range of the HackRF One.
+pub const HACKRF_MIN_FREQ: u64 = 1_000_000;
+pub const HACKRF_MAX_FREQ: u64 = 6_000_000_000;
+/// Below 2 Msps the HackRF's ADC aliases badly; above 20 Msps USB 2.0
+/// drops samples.
+pub const HACKRF_MIN_SAMPLE_RATE: u32 = 2_000_000;
+pub const HACKRF_MAX_SAMPLE_RATE: u32 = 20_000_000;
+
+/// What produced the samples, for the scan report.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct DeviceInfo {
+    pub driver: String,
+    pub serial: Option<String>,
+    pub board_id: Option<u8>,
+    pub firmware_version: Option<String>,
+}
+
+/// Anything that can be tuned and streams interleaved signed 8-bit I/Q,
+/// the format the HackRF delivers from `rx()`. Sources are driven from
+/// their own capture thread.
+pub trait SdrSource: Send {
+    fn tune(&mut self, frequency: u64) -> Result<(), ScanError>;
+    fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), ScanError>;
+    fn set_gains(&mut self, gains: Gains) -> Result<(), ScanError>;
+    /// Returns the next block of samples, starting the stream if needed.
+    /// An empty block means the source has nothing more to give.
+    fn read_block(&mut self) -> Result<Vec<u8>, ScanError>;
+    /// Whether `set_gains` changes what comes out of `read_block`.
+    fn supports_gain(&self) -> bool {
+        true
+    }
+    /// Whether samples keep arriving whether or not they are read, so a
+    /// reader that falls behind loses them rather than holding the source up.
+    fn realtime(&self) -> bool {
+        true
+    }
+    fn device_info(&self) -> DeviceInfo;
+    /// Drops the connection and opens the device again with the current
+    /// settings, after it has reset or gone missing.
+    fn reconnect(&mut self) -> Result<(), ScanError> {
+        Ok(())
+    }
+}
+
+/// The HackRF attached, if there is one. The driver only ever opens the
+/// first device, so at most one is found.
+pub fn find_hackrf() -> Option<DeviceInfo> {
+    let radio: HackRfOne<UnknownMode> = HackRfOne::new()?;
+    Some(hackrf_info(radio.board_id().ok(), radio.version().ok()))
+}
+
+// The driver does not read back the serial number, so only what it does
+// expose is reported
+fn hackrf_info(board_id: Option<u8>, firmware_version: Option<String>) -> DeviceInfo {
+    DeviceInfo {
+        driver: "hackrf".to_string(),
+        serial: None,
+        board_id,
+        firmware_version,
+    }
+}
+
+// Blocks thrown away after every retune while the PLL and AGC settle
+const SETTLE_BLOCKS: usize = 1;
+
+enum Radio {
+    Idle(HackRfOne<UnknownMode>),
+    Streaming(HackRfOne<RxMode>),
+}
+
+/// One HackRF kept open for the whole sweep. Retuning stops the stream,
+/// sets the new frequency and restarts it instead of reopening the device.
+#[derive(Default)]
+pub struct HackRfSource {
+    radio: Option<Radio>,
+    frequency: u64,
+    sample_rate: u32,
+    gains: Gains,
+}
+
+impl HackRfSource {
+    fn open(&self) -> Result<HackRfOne<UnknownMode>, ScanError> {
+        let mut radio: HackRfOne<UnknownMode> =
+            HackRfOne::new().ok_or(ScanError::DeviceNotFound)?;
+
+        radio
+            .set_freq(self.frequency)
+            .map_err(|e| ScanError::Tuning(format!("frequency {} Hz: {:?}", self.frequency, e)))?;
+        radio.set_sample_rate(self.sample_rate, 1).map_err(|e| {
+            ScanError::Tuning(format!(
+                "sample rate {} samples/s: {:?}",
+                self.sample_rate, e
+            ))
+        })?;
+        apply_gains(&mut radio, self.gains)?;
+        Ok(radio)
+    }
+
+    /// Leaves the device open but not streaming, opening it if needed.
+    fn idle(&mut self) -> Result<HackRfOne<UnknownMode>, ScanError> {
+        match self.radio.take() {
+            Some(Radio::Idle(radio)) => Ok(radio),
+            Some(Radio::Streaming(radio)) => Ok(radio
+                .stop_rx()
+                .map_err(|e| ScanError::Usb(format!("stopping RX: {:?}", e)))?),
+            None => self.open(),
+        }
+    }
+
+    fn streaming(&mut self) -> Result<&mut HackRfOne<RxMode>, ScanError> {
+        if !matches!(self.radio, Some(Radio::Streaming(_))) {
+            let radio = self
+                .idle()?
+                .into_rx_mode()
+                .map_err(|e| ScanError::Usb(format!("entering RX mode: {:?}", e)))?;
+            self.radio = Some(Radio::Streaming(radio));
+        }
+        match self.radio.as_mut() {
+            Some(Radio::Streaming(radio)) => Ok(radio),
+            _ => unreachable!("radio was just put in RX mode"),
+        }
+    }
+}
+
+fn apply_gains(radio: &mut HackRfOne<UnknownMode>, gains: Gains) -> Result<(), ScanError> {
+    radio
+        .set_amp_enable(gains.amp)
+        .map_err(|e| ScanError::Tuning(format!("amplifier: {:?}", e)))?;
+    radio
+        .set_lna_gain(gains.lna)
+        .map_err(|e| ScanError::Tuning(format!("LNA gain {} dB: {:?}", gains.lna, e)))?;
+    radio
+        .set_vga_gain(gains.vga)
+        .map_err(|e| ScanError::Tuning(format!("VGA gain {} dB: {:?}", gains.vga, e)))?;
+    Ok(())
+}
+
+impl SdrSource for HackRfSource {
+    fn tune(&mut self, frequency: u64) -> Result<(), ScanError> {
+        self.frequency = frequency;
+        let mut radio = self.idle()?;
+        radio
+            .set_freq(frequency)
+            .map_err(|e| ScanError::Tuning(format!("frequency {} Hz: {:?}", self.frequency, e)))?;
+        self.radio = Some(Radio::Idle(radio));
+
+        let radio = self.streaming()?;
+        for _ in 0..SETTLE_BLOCKS {
+            radio
+                .rx()
+                .map_err(|e| ScanError::Usb(format!("receiving samples: {:?}", e)))?;
+        }
+        Ok(())
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), ScanError> {
+        if sample_rate == self.sample_rate && self.radio.is_some() {
+            return Ok(());
+        }
+        self.sample_rate = sample_rate;
+        let mut radio = self.idle()?;
+        radio.set_sample_rate(sample_rate, 1).map_err(|e| {
+            ScanError::Tuning(format!(
+                "sample rate {} samples/s: {:?}",
+                self.sample_rate, e
+            ))
+        })?;
+        self.radio = Some(Radio::Idle(radio));
+        Ok(())
+    }
+
+    fn set_gains(&mut self, gains: Gains) -> Result<(), ScanError> {
+        if gains == self.gains && self.radio.is_some() {
+            return Ok(());
+        }
+        self.gains = gains;
+        let mut radio = self.idle()?;
+        apply_gains(&mut radio, gains)?;
+        self.radio = Some(Radio::Idle(radio));
+        Ok(())
+    }
+
+    fn read_block(&mut self) -> Result<Vec<u8>, ScanError> {
+        let samples = self
+            .streaming()?
+            .rx()
+            .map_err(|e| ScanError::Usb(format!("receiving samples: {:?}", e)))?;
+        Ok(samples)
+    }
+
+    fn reconnect(&mut self) -> Result<(), ScanError> {
+        // Whatever is left of the old handle may not answer any more
+        if let Some(Radio::Streaming(radio)) = self.radio.take() {
+            let _ = radio.stop_rx();
+        }
+        let radio = self.open()?;
+        self.radio = Some(Radio::Idle(radio));
+        Ok(())
+    }
+
+    fn device_info(&self) -> DeviceInfo {
+        let (board_id, firmware_version) = match &self.radio {
+            Some(Radio::Idle(radio)) => (radio.board_id().ok(), radio.version().ok()),
+            Some(Radio::Streaming(radio)) => (radio.board_id().ok(), radio.version().ok()),
+            None => (None, None),
+        };
+        hackrf_info(board_id, firmware_version)
+    }
+}
+
+impl Drop for HackRfSource {
+    fn drop(&mut self) {
+        if let Some(Radio::Streaming(radio)) = self.radio.take() {
+            let _ = radio.stop_rx();
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::dsp::power_to_dbfs;
+
+const FFT_SIZE: usize = 1024;
+
+/// Welch-averaged power spectrum of one capture, ordered from the lowest to
+/// the highest frequency. Each bin holds the share of the total power that
+/// falls in it, so summing bins gives power in dBFS terms.
+pub struct Spectrum {
+    pub center_freq: u64,
+    pub sample_rate: u32,
+    pub bins: Vec<f64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelPower {
+    pub freq: u64,
+    pub power_dbfs: f64,
+    pub peak_bin_dbfs: f64,
+}
+
+impl Spectrum {
+    pub fn bin_width(&self) -> f64 {
+        self.sample_rate as f64 / self.bins.len() as f64
+    }
+
+    fn bin_freq(&self, bin: usize) -> f64 {
+        self.center_freq as f64 - self.sample_rate as f64 / 2.0 + bin as f64 * self.bin_width()
+    }
+
+    /// Bins whose frequency falls inside the channel of the given width
+    /// centred on `freq`.
+    pub fn channel_bins(&self, freq: u64, width: u64) -> Range<usize> {
+        let low = freq as f64 - width as f64 / 2.0;
+        let high = freq as f64 + width as f64 / 2.0;
+        let inside = |bin: &usize| {
+            let bin_freq = self.bin_freq(*bin);
+            bin_freq >= low && bin_freq < high
+        };
+        let start = (0..self.bins.len()).find(inside).unwrap_or(self.bins.len());
+        let end = (start..self.bins.len())
+            .find(|bin| !inside(bin))
+            .unwrap_or(self.bins.len());
+        start..end
+    }
+
+    /// Integrates power over each channel of the given width centred on
+    /// the given frequencies.
+    pub fn channel_powers(&self, channels: &[u64], width: u64) -> Vec<ChannelPower> {
+        channels
+            .iter()
+            .map(|&freq| {
+                let mut power = 0.0;
+                let mut peak: f64 = 0.0;
+                for &value in &self.bins[self.channel_bins(freq, width)] {
+                    power += value;
+                    peak = peak.max(value);
+                }
+                ChannelPower {
+                    freq,
+                    power_dbfs: power_to_dbfs(power),
+                    peak_bin_dbfs: power_to_dbfs(peak),
+                }
+            })
+            .collect()
+    }
+}
+
+fn hann_window() -> (Vec<f32>, f32) {
+    let window: Vec<f32> = (0..FFT_SIZE)
+        .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / FFT_SIZE as f32).cos())
+        .collect();
+    let power = window.iter().map(|w| w * w).sum();
+    (window, power)
+}
+
+/// Puts the FFT output in frequency order and scales it.
+fn arrange_bins(bins: &[f64], scale: f64) -> Vec<f64> {
+    let mut shifted: Vec<f64> = bins[FFT_SIZE / 2..]
+        .iter()
+        .chain(&bins[..FFT_SIZE / 2])
+        .map(|power| power / scale)
+        .collect();
+
+    // Paper over the HackRF's DC spike with its neighbours
+    let dc = FFT_SIZE / 2;
+    let fill = (shifted[dc - 2] + shifted[dc + 2]) / 2.0;
+    shifted[dc - 1..=dc + 1].fill(fill);
+    shifted
+}
+
+/// Welch-averaged spectrum fed a block at a time. Only the samples of the
+/// segment still being filled are held between blocks, so it needs the
+/// same memory however long the dwell. Alongside the average it keeps the
+/// highest power each channel reached in any one segment.
+pub struct WelchSpectrum {
+    center_freq: u64,
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    window_power: f32,
+    pending: Vec<Complex<f32>>,
+    buffer: Vec<Complex<f32>>,
+    bins: Vec<f64>,
+    segments: usize,
+    channel_bins: Vec<Range<usize>>,
+    channel_peaks: Vec<f64>,
+}
+
+impl WelchSpectrum {
+    /// `channels` and `width` pick the channels whose peaks are followed.
+    pub fn new(center_freq: u64, sample_rate: u32, channels: &[u64], width: u64) -> Self {
+        let (window, window_power) = hann_window();
+        let layout = Spectrum {
+            center_freq,
+            sample_rate,
+            bins: vec![0.0; FFT_SIZE],
+        };
+        WelchSpectrum {
+            center_freq,
+            sample_rate,
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            window_power,
+            pending: Vec::new(),
+            buffer: vec![Complex::new(0.0, 0.0); FFT_SIZE],
+            bins: vec![0.0; FFT_SIZE],
+            segments: 0,
+            channel_bins: channels
+                .iter()
+                .map(|&freq| layout.channel_bins(freq, width))
+                .collect(),
+            channel_peaks: vec![0.0; channels.len()],
+        }
+    }
+
+    pub fn add(&mut self, samples: &[Complex<f32>]) {
+        self.pending.extend_from_slice(samples);
+
+        // Hann window with 50% overlap
+        let mut start = 0;
+        while self.pending.len() - start >= FFT_SIZE {
+            for ((slot, sample), w) in self
+                .buffer
+                .iter_mut()
+                .zip(&self.pending[start..start + FFT_SIZE])
+                .zip(&self.window)
+            {
+                *slot = sample * w;
+            }
+            self.fft.process(&mut self.buffer);
+            let powers: Vec<f64> = self.buffer.iter().map(|v| v.norm_sqr() as f64).collect();
+            for (bin, power) in self.bins.iter_mut().zip(&powers) {
+                *bin += power;
+            }
+            let segment = arrange_bins(&powers, FFT_SIZE as f64 * self.window_power as f64);
+            for (peak, bins) in self.channel_peaks.iter_mut().zip(&self.channel_bins) {
+                *peak = peak.max(segment[bins.clone()].iter().sum());
+            }
+            self.segments += 1;
+            start += FFT_SIZE / 2;
+        }
+        self.pending.drain(..start);
+    }
+
+    /// The average so far, or `None` until a whole segment has come in.
+    pub fn spectrum(&self) -> Option<Spectrum> {
+        if self.segments == 0 {
+            return None;
+        }
+
+        let scale = self.segments as f64 * FFT_SIZE as f64 * self.window_power as f64;
+        Some(Spectrum {
+            center_freq: self.center_freq,
+            sample_rate: self.sample_rate,
+            bins: arrange_bins(&self.bins, scale),
+        })
+    }
+
+    /// Highest power in dBFS each channel reached in a single segment, in
+    /// the order the channels were given.
+    pub fn channel_peaks(&self) -> Vec<f64> {
+        self.channel_peaks
+            .iter()
+            .map(|&peak| power_to_dbfs(peak))
+            .collect()
+    }
+}
+
+/// One spectrum per back-to-back FFT frame, for following power over time
+/// rather than averaging it away. Fed a block at a time, handing back the
+/// frames each block completes.
+pub struct FrameSpectra {
+    center_freq: u64,
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    scale: f64,
+    pending: Vec<Complex<f32>>,
+}
+
+impl FrameSpectra {
+    pub fn new(center_freq: u64, sample_rate: u32) -> Self {
+        let (window, window_power) = hann_window();
+        FrameSpectra {
+            center_freq,
+            sample_rate,
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            scale: FFT_SIZE as f64 * window_power as f64,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, samples: &[Complex<f32>]) -> Vec<Spectrum> {
+        self.pending.extend_from_slice(samples);
+
+        let frames = self.pending.len() / FFT_SIZE;
+        let spectra = self.pending[..frames * FFT_SIZE]
+            .chunks_exact(FFT_SIZE)
+            .map(|frame| {
+                let mut buffer: Vec<Complex<f32>> = frame
+                    .iter()
+                    .zip(&self.window)
+                    .map(|(sample, w)| sample * w)
+                    .collect();
+                self.fft.process(&mut buffer);
+                let bins: Vec<f64> = buffer.iter().map(|value| value.norm_sqr() as f64).collect();
+                Spectrum {
+                    center_freq: self.center_freq,
+                    sample_rate: self.sample_rate,
+                    bins: arrange_bins(&bins, self.scale),
+                }
+            })
+            .collect();
+        self.pending.drain(..frames * FFT_SIZE);
+        spectra
+    }
+}
+
+/// Time covered by each of the short-time spectra.
+pub fn frame_duration(sample_rate: u32) -> f64 {
+    FFT_SIZE as f64 / sample_rate as f64
+}
@@ -0,0 +1,160 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::bsch::SyncInfo;
+use crate::burst::{
+    BurstKind, Classification, FIRST_HALF_OFFSET, HALF_SLOT_BITS, SECOND_HALF_OFFSET,
+};
+use crate::carrier::Carrier;
+use crate::coding::{decode_block, field, scrambling_code, HALF_SLOT_INTERLEAVE_A};
+
+const MAC_BROADCAST: u32 = 0b10;
+const BROADCAST_SYSINFO: u32 = 0b00;
+
+/// The cell configuration broadcast in SYSINFO and the D-MLE-SYSINFO it
+/// carries (EN 300 392-2 clause 21.4.4.1 and 18.4.2.2).
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SysInfo {
+    pub main_carrier: u16,
+    pub frequency_band: u8,
+    /// Carrier offset code: 0 none, 1 +6.25 kHz, 2 -6.25 kHz, 3 +12.5 kHz
+    pub offset: u8,
+    pub duplex_spacing: u8,
+    pub reverse_operation: bool,
+    pub common_secondary_control_channels: u8,
+    /// None where the cell sends the reserved value
+    pub ms_tx_power_max_dbm: Option<u8>,
+    pub rx_level_access_min_dbm: i16,
+    pub location_area: u16,
+    pub encryption: bool,
+    /// 1 for clear, 2 for static cipher keys, 3 for dynamic keys (a common
+    /// cipher key identifier is being broadcast)
+    pub security_class: u8,
+}
+
+impl SysInfo {
+    pub fn main_carrier(&self) -> Carrier {
+        Carrier {
+            band: self.frequency_band,
+            number: self.main_carrier,
+            offset: self.offset,
+        }
+    }
+}
+
+fn parse(pdu: &[u8]) -> Option<SysInfo> {
+    if field(pdu, 0, 2) != MAC_BROADCAST || field(pdu, 2, 2) != BROADCAST_SYSINFO {
+        return None;
+    }
+
+    let power = field(pdu, 28, 3) as u8;
+    let cipher_key_flag = field(pdu, 43, 1) == 1;
+    let encryption = field(pdu, 122, 1) == 1;
+    let security_class = match (encryption, cipher_key_flag) {
+        (false, _) => 1,
+        (true, false) => 2,
+        (true, true) => 3,
+    };
+
+    Some(SysInfo {
+        main_carrier: field(pdu, 4, 12) as u16,
+        frequency_band: field(pdu, 16, 4) as u8,
+        offset: field(pdu, 20, 2) as u8,
+        duplex_spacing: field(pdu, 22, 3) as u8,
+        reverse_operation: field(pdu, 25, 1) == 1,
+        common_secondary_control_channels: field(pdu, 26, 2) as u8,
+        ms_tx_power_max_dbm: (power != 0).then_some(10 + 5 * power),
+        rx_level_access_min_dbm: -125 + 5 * field(pdu, 31, 4) as i16,
+        // Access parameter, downlink timeout, hyperframe or cipher key
+        // identifier and the optional field sit in between
+        location_area: field(pdu, 82, 14) as u16,
+        encryption,
+        security_class,
+    })
+}
+
+/// Looks through the half-slot blocks of a carrier's synchronisation and
+/// split normal bursts for a SYSINFO broadcast.
+pub fn find_sysinfo(
+    bits: &[u8],
+    classification: &Classification,
+    sync: &SyncInfo,
+) -> Option<SysInfo> {
+    let seed = scrambling_code(sync.mcc, sync.mnc, sync.colour_code);
+
+    classification
+        .bursts
+        .iter()
+        .flat_map(|burst| match burst.kind {
+            BurstKind::Sync => vec![burst.start + SECOND_HALF_OFFSET],
+            BurstKind::NormalSplit => vec![
+                burst.start + FIRST_HALF_OFFSET,
+                burst.start + SECOND_HALF_OFFSET,
+            ],
+            BurstKind::NormalFull => Vec::new(),
+        })
+        .filter_map(|offset| bits.get(offset..offset + HALF_SLOT_BITS))
+        .filter_map(|block| decode_block(block, seed, HALF_SLOT_INTERLEAVE_A))
+        .find_map(|pdu| parse(&pdu))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out fields of (width, value) one after another, most
+    /// significant bit first.
+    fn pdu(fields: &[(usize, u32)]) -> Vec<u8> {
+        fields
+            .iter()
+            .flat_map(|&(width, value)| (0..width).rev().map(move |i| (value >> i) as u8 & 1))
+            .collect()
+    }
+
+    #[test]
+    fn parses_sysinfo_fields_in_order() {
+        // SYSINFO (clause 21.4.4.1) followed by D-MLE-SYSINFO (18.4.2.2)
+        let bits = pdu(&[
+            (2, MAC_BROADCAST),
+            (2, BROADCAST_SYSINFO),
+            (12, 3601), // main carrier
+            (4, 4),     // frequency band
+            (2, 1),     // offset
+            (3, 2),     // duplex spacing
+            (1, 1),     // reverse operation
+            (2, 0),     // common secondary control channels
+            (3, 5),     // MS_TXPWR_MAX_CELL
+            (4, 3),     // RXLEV_ACCESS_MIN
+            (4, 0),     // access parameter
+            (4, 0),     // radio downlink timeout
+            (1, 1),     // cipher key identifier follows
+            (16, 0xBEEF),
+            (2, 0),       // optional field flag
+            (20, 0),      // optional field value
+            (14, 0x2A5C), // location area
+            (16, 0xFFFF), // subscriber class
+            (10, 0),      // BS service details up to encryption
+            (1, 1),       // air interface encryption
+            (1, 0),       // advanced link
+        ]);
+
+        let info = parse(&bits).expect("SYSINFO not recognised");
+        assert_eq!(info.main_carrier, 3601);
+        assert_eq!(info.frequency_band, 4);
+        assert_eq!(info.offset, 1);
+        assert_eq!(info.duplex_spacing, 2);
+        assert!(info.reverse_operation);
+        assert_eq!(info.ms_tx_power_max_dbm, Some(35));
+        assert_eq!(info.rx_level_access_min_dbm, -110);
+        assert_eq!(info.location_area, 0x2A5C);
+        assert!(info.encryption);
+        assert_eq!(info.security_class, 3);
+    }
+
+    #[test]
+    fn ignores_other_broadcasts() {
+        let mut bits = pdu(&[(2, MAC_BROADCAST), (2, 0b01)]);
+        bits.resize(124, 0);
+        assert_eq!(parse(&bits), None);
+    }
+}
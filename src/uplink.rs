@@ -0,0 +1,282 @@
+use num_complex::Complex;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::activity::{ActivityInterval, ScanClock};
+use crate::band::{Band, Step};
+use crate::detect::{percentile, DetectionConfig};
+use crate::dsp::power_to_dbfs;
+use crate::spectrum::{frame_duration, FrameSpectra};
+
+// Once started, a burst holds until its channel drops this far below the
+// level that triggered it, so ramping and fading do not chop it up
+const HYSTERESIS_DB: f64 = 3.0;
+// Quiet spells up to this long are bridged rather than ending the burst
+const MAX_GAP: Duration = Duration::from_millis(1);
+// Shorter than this is an impulse, not even a half-slot transmission
+const MIN_BURST: Duration = Duration::from_millis(2);
+// The floor is learnt from this many frames, about a second at 2 Msps, and
+// held for the rest of the dwell, so only their channel powers are kept
+const FLOOR_FRAMES: usize = 2048;
+
+/// An uplink channel that carried at least one burst during a capture.
+pub struct UplinkActivity {
+    pub freq: u64,
+    pub noise_floor_dbfs: f64,
+    /// One interval per mobile transmission
+    pub bursts: Vec<ActivityInterval>,
+}
+
+/// Where the frames of one capture sit on the scan's timeline.
+struct FrameTiming<'a> {
+    clock: &'a ScanClock,
+    /// Capture start relative to the scan
+    offset: Duration,
+    frame: Duration,
+}
+
+impl FrameTiming<'_> {
+    fn at(&self, frame: usize) -> Duration {
+        self.offset + self.frame * frame as u32
+    }
+}
+
+/// Levels a channel is judged against once the floor is known.
+struct Thresholds {
+    floor_dbfs: f64,
+    start_dbfs: f64,
+    hold_dbfs: f64,
+    /// Quiet frames bridged within a burst
+    max_gap: usize,
+}
+
+/// A burst still being followed, with running totals over the frames it
+/// spans.
+struct OpenBurst {
+    first: usize,
+    last: usize,
+    frames: usize,
+    sum: f64,
+    peak: f64,
+    // Quiet frames since `last`, only counted in if the burst picks up again
+    gap_frames: usize,
+    gap_sum: f64,
+    gap_peak: f64,
+}
+
+impl OpenBurst {
+    fn new(frame: usize, power: f64) -> Self {
+        OpenBurst {
+            first: frame,
+            last: frame,
+            frames: 1,
+            sum: power,
+            peak: power,
+            gap_frames: 0,
+            gap_sum: 0.0,
+            gap_peak: 0.0,
+        }
+    }
+
+    fn extend(&mut self, frame: usize, power: f64) {
+        self.frames += self.gap_frames + 1;
+        self.sum += self.gap_sum + power;
+        self.peak = self.peak.max(self.gap_peak).max(power);
+        self.last = frame;
+        self.gap_frames = 0;
+        self.gap_sum = 0.0;
+        self.gap_peak = 0.0;
+    }
+
+    fn bridge(&mut self, power: f64) {
+        self.gap_frames += 1;
+        self.gap_sum += power;
+        self.gap_peak = self.gap_peak.max(power);
+    }
+
+    fn summarise(&self, timing: &FrameTiming) -> Option<ActivityInterval> {
+        if timing.frame * (self.frames as u32) < MIN_BURST {
+            return None;
+        }
+        Some(ActivityInterval::new(
+            timing.clock,
+            timing.at(self.first),
+            timing.at(self.last + 1),
+            power_to_dbfs(self.peak),
+            power_to_dbfs(self.sum / self.frames as f64),
+        ))
+    }
+}
+
+/// Follows one channel frame by frame: a burst starts above the detection
+/// threshold and lasts until the channel stays below the hysteresis level
+/// for longer than a short gap.
+#[derive(Default)]
+struct ChannelTrack {
+    open: Option<OpenBurst>,
+    bursts: Vec<ActivityInterval>,
+}
+
+impl ChannelTrack {
+    fn add(&mut self, frame: usize, power: f64, thresholds: &Thresholds, timing: &FrameTiming) {
+        let level = power_to_dbfs(power);
+        self.open = match self.open.take() {
+            None if level > thresholds.start_dbfs => Some(OpenBurst::new(frame, power)),
+            Some(mut burst) if level >= thresholds.hold_dbfs => {
+                burst.extend(frame, power);
+                Some(burst)
+            }
+            Some(burst) if frame - burst.last > thresholds.max_gap => {
+                self.bursts.extend(burst.summarise(timing));
+                None
+            }
+            Some(mut burst) => {
+                burst.bridge(power);
+                Some(burst)
+            }
+            None => None,
+        };
+    }
+
+    fn finish(mut self, timing: &FrameTiming) -> Vec<ActivityInterval> {
+        if let Some(burst) = self.open.take() {
+            self.bursts.extend(burst.summarise(timing));
+        }
+        self.bursts
+    }
+}
+
+/// Looks for mobile bursts on every channel of one uplink step, fed the
+/// capture a block at a time. Apart from the frames the floor is learnt
+/// from, it keeps no more than each channel's current burst.
+pub struct BurstDetector<'a> {
+    step: &'a Step,
+    raster: u64,
+    config: &'a DetectionConfig,
+    timing: FrameTiming<'a>,
+    spectra: FrameSpectra,
+    channel_bins: Vec<Range<usize>>,
+    // Channel powers of each frame, until there are enough for the floor
+    warmup: Vec<Vec<f64>>,
+    thresholds: Option<Thresholds>,
+    tracks: Vec<ChannelTrack>,
+    frames: usize,
+}
+
+impl<'a> BurstDetector<'a> {
+    /// `offset` is when the capture started relative to the scan, so burst
+    /// times line up across steps.
+    pub fn new(
+        band: &Band,
+        step: &'a Step,
+        config: &'a DetectionConfig,
+        clock: &'a ScanClock,
+        offset: Duration,
+    ) -> Self {
+        BurstDetector {
+            step,
+            raster: band.raster,
+            config,
+            timing: FrameTiming {
+                clock,
+                offset,
+                frame: Duration::from_secs_f64(frame_duration(band.sample_rate)),
+            },
+            spectra: FrameSpectra::new(step.center_freq, band.sample_rate),
+            channel_bins: Vec::new(),
+            warmup: Vec::new(),
+            thresholds: None,
+            tracks: step
+                .channels
+                .iter()
+                .map(|_| ChannelTrack::default())
+                .collect(),
+            frames: 0,
+        }
+    }
+
+    pub fn add(&mut self, iq: &[Complex<f32>]) {
+        for spectrum in self.spectra.add(iq) {
+            if self.channel_bins.is_empty() {
+                self.channel_bins = self
+                    .step
+                    .channels
+                    .iter()
+                    .map(|&freq| spectrum.channel_bins(freq, self.raster))
+                    .collect();
+            }
+            let powers: Vec<f64> = self
+                .channel_bins
+                .iter()
+                .map(|bins| spectrum.bins[bins.clone()].iter().sum())
+                .collect();
+
+            if self.thresholds.is_some() {
+                self.track(&powers);
+            } else {
+                self.warmup.push(powers);
+                if self.warmup.len() >= FLOOR_FRAMES {
+                    self.learn_floor();
+                }
+            }
+        }
+    }
+
+    fn learn_floor(&mut self) {
+        // The uplink is quiet most of the time, so the floor comes from
+        // every channel and frame together
+        let mut levels: Vec<f64> = self
+            .warmup
+            .iter()
+            .flatten()
+            .map(|&p| power_to_dbfs(p))
+            .collect();
+        let Some(floor_dbfs) = percentile(&mut levels, self.config.noise_percentile) else {
+            return;
+        };
+        let start_dbfs = floor_dbfs + self.config.snr_margin_db;
+        self.thresholds = Some(Thresholds {
+            floor_dbfs,
+            start_dbfs,
+            hold_dbfs: start_dbfs - HYSTERESIS_DB,
+            max_gap: (MAX_GAP.as_secs_f64() / self.timing.frame.as_secs_f64()).ceil() as usize,
+        });
+        for powers in std::mem::take(&mut self.warmup) {
+            self.track(&powers);
+        }
+    }
+
+    fn track(&mut self, powers: &[f64]) {
+        let Some(thresholds) = &self.thresholds else {
+            return;
+        };
+        for (track, &power) in self.tracks.iter_mut().zip(powers) {
+            track.add(self.frames, power, thresholds, &self.timing);
+        }
+        self.frames += 1;
+    }
+
+    /// The channels that carried at least one burst.
+    pub fn finish(mut self) -> Vec<UplinkActivity> {
+        if self.thresholds.is_none() {
+            self.learn_floor();
+        }
+        let Some(thresholds) = &self.thresholds else {
+            return Vec::new();
+        };
+        let timing = &self.timing;
+        self.step
+            .channels
+            .iter()
+            .zip(self.tracks)
+            .filter_map(|(&freq, track)| {
+                let bursts = track.finish(timing);
+                (!bursts.is_empty()).then_some(UplinkActivity {
+                    freq,
+                    noise_floor_dbfs: thresholds.floor_dbfs,
+                    bursts,
+                })
+            })
+            .collect()
+    }
+}